@@ -1,6 +1,18 @@
-use std::{fmt::Display, ops::Index};
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    io::Write,
+    net::{TcpListener, TcpStream},
+    ops::Index,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
+
+use unicode_width::UnicodeWidthStr;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 enum DieRoll {
     One = 1,
     Two,
@@ -10,6 +22,12 @@ enum DieRoll {
     Six,
 }
 
+impl DieRoll {
+    fn from_u8(face: u8) -> Option<DieRoll> {
+        DIE_ROLLS.get(usize::from(face).checked_sub(1)?).copied()
+    }
+}
+
 impl Display for DieRoll {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -32,6 +50,16 @@ const DIE_ROLLS: [DieRoll; 6] = [
     DieRoll::Six,
 ];
 
+const TINY_STRAIGHTS: [[DieRoll; 3]; 4] = {
+    use DieRoll::*;
+    [
+        [One, Two, Three],
+        [Two, Three, Four],
+        [Three, Four, Five],
+        [Four, Five, Six],
+    ]
+};
+
 const SMALL_STRAIGHTS: [[DieRoll; 4]; 3] = {
     use DieRoll::*;
     [
@@ -48,6 +76,334 @@ const LARGE_STRAIGHTS: [[DieRoll; 5]; 2] = {
 
 type DiceRolls = [DieRoll; 5];
 
+trait DiceSource {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()>;
+}
+
+// the dice a turn starts with; once every box is filled nothing is rolled,
+// so a finished game doesn't wait for dice nobody will use
+fn roll_turn(
+    source: &mut dyn DiceSource,
+    player_state: &PlayerState,
+    rules: &Rules,
+) -> std::io::Result<DiceRolls> {
+    let mut dice: DiceRolls = [DieRoll::One; 5];
+    if !player_state.is_done(rules) {
+        source.roll(&mut dice, &[0, 1, 2, 3, 4])?;
+    }
+    Ok(dice)
+}
+
+struct RandomDice;
+
+impl DiceSource for RandomDice {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        for &idx in indices {
+            dice[idx] = DIE_ROLLS[fastrand::usize(..6)];
+        }
+        Ok(())
+    }
+}
+
+struct SeededDice(fastrand::Rng);
+
+impl DiceSource for SeededDice {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        for &idx in indices {
+            dice[idx] = DIE_ROLLS[self.0.usize(..6)];
+        }
+        Ok(())
+    }
+}
+
+// Loaded dice: every face comes up in proportion to its weight.
+struct WeightedDice {
+    rng: fastrand::Rng,
+    weights: [u32; 6],
+}
+
+impl DiceSource for WeightedDice {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        let total: u32 = self.weights.iter().sum();
+        for &idx in indices {
+            let mut pick = self.rng.u32(..total);
+            for (die, &weight) in DIE_ROLLS.iter().zip(&self.weights) {
+                if pick < weight {
+                    dice[idx] = *die;
+                    break;
+                }
+                pick -= weight;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_weights(input: &str) -> Result<[u32; 6], String> {
+    let weights = input
+        .split(',')
+        .map(|weight| match weight.trim().parse() {
+            Ok(weight) if weight > 0 => Ok(weight),
+            _ => Err(format!("invalid dice weight: {weight}")),
+        })
+        .collect::<Result<Vec<u32>, _>>()?;
+    if weights
+        .iter()
+        .try_fold(0u32, |sum, &w| sum.checked_add(w))
+        .is_none()
+    {
+        return Err("dice weights are too large".to_string());
+    }
+    let found = weights.len();
+    weights
+        .try_into()
+        .map_err(|_| format!("expected 6 dice weights, got {found}"))
+}
+
+fn random_source(seed: Option<u64>, weights: Option<[u32; 6]>) -> Box<dyn DiceSource> {
+    let rng = match seed {
+        Some(seed) => fastrand::Rng::with_seed(seed),
+        None => fastrand::Rng::new(),
+    };
+    match (seed, weights) {
+        (_, Some(weights)) => Box::new(WeightedDice { rng, weights }),
+        (Some(_), None) => Box::new(SeededDice(rng)),
+        (None, None) => Box::new(RandomDice),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum GameError {
+    AlreadyRecorded(Combination),
+    InvalidDie(char),
+    FaceOutOfRange(u32),
+    WrongDiceCount { expected: usize, found: usize },
+    UnknownCombination(String),
+    DisabledCombination(Combination),
+    MissingCombination(Combination),
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameError::AlreadyRecorded(combination) => {
+                write!(f, "{combination} has already been recorded")
+            }
+            GameError::InvalidDie(c) => write!(f, "'{c}' is not a die face"),
+            GameError::FaceOutOfRange(face) => {
+                write!(f, "{face} is not a die face, dice show 1 to 6")
+            }
+            GameError::WrongDiceCount { expected, found } => {
+                write!(f, "expected {expected} dice, got {found}")
+            }
+            GameError::UnknownCombination(name) => write!(f, "unknown combination: {name}"),
+            GameError::DisabledCombination(combination) => {
+                write!(f, "{combination} is not part of these rules")
+            }
+            GameError::MissingCombination(combination) => {
+                write!(f, "{combination} has not been assigned")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+// Faces are either separated by spaces or commas, e.g. `1 3 3 4 6`, or all
+// written together, e.g. `13346`.
+fn parse_faces(input: &str, n: usize) -> Result<Vec<DieRoll>, GameError> {
+    let input = input.trim();
+    let separated = input.contains(|c: char| c.is_whitespace() || c == ',');
+    let faces = if separated {
+        input
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                if let Some(c) = token.chars().find(|c| !c.is_ascii_digit()) {
+                    return Err(GameError::InvalidDie(c));
+                }
+                let face = token.parse().unwrap_or(u32::MAX);
+                u8::try_from(face)
+                    .ok()
+                    .and_then(DieRoll::from_u8)
+                    .ok_or(GameError::FaceOutOfRange(face))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        input
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(digit) => {
+                    DieRoll::from_u8(digit as u8).ok_or(GameError::FaceOutOfRange(digit))
+                }
+                None => Err(GameError::InvalidDie(c)),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    if faces.len() != n {
+        return Err(GameError::WrongDiceCount {
+            expected: n,
+            found: faces.len(),
+        });
+    }
+    Ok(faces)
+}
+
+fn parse_dice(input: &str) -> Result<DiceRolls, GameError> {
+    let faces = parse_faces(input, 5)?;
+    Ok(faces.try_into().expect("there are five faces"))
+}
+
+struct RepeatWatch {
+    inner: Box<dyn DiceSource>,
+    recent: VecDeque<DiceRolls>,
+}
+
+impl RepeatWatch {
+    const WINDOW: usize = 10;
+    const MAX_REPEATS: usize = 3;
+
+    fn new(inner: Box<dyn DiceSource>) -> Self {
+        Self {
+            inner,
+            recent: VecDeque::with_capacity(Self::WINDOW),
+        }
+    }
+}
+
+impl DiceSource for RepeatWatch {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        self.inner.roll(dice, indices)?;
+        // only fresh rolls of all dice are comparable, rerolls keep some dice
+        if indices.len() == dice.len() {
+            let mut roll = *dice;
+            roll.sort();
+            if self.recent.len() == Self::WINDOW {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(roll);
+            let repeats = self.recent.iter().filter(|&&r| r == roll).count();
+            if repeats >= Self::MAX_REPEATS {
+                let faces: Vec<String> = roll.iter().map(|die| die.to_string()).collect();
+                eprintln!(
+                    "warning: {} was rolled {repeats} times in the last {} rolls",
+                    faces.join(" "),
+                    self.recent.len()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+struct FaceTally {
+    inner: Box<dyn DiceSource>,
+    counts: [u32; 6],
+}
+
+impl FaceTally {
+    fn new(inner: Box<dyn DiceSource>) -> Self {
+        Self {
+            inner,
+            counts: [0; 6],
+        }
+    }
+
+    fn count(&self, die: DieRoll) -> u32 {
+        self.counts[die as usize - 1]
+    }
+}
+
+impl DiceSource for FaceTally {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        self.inner.roll(dice, indices)?;
+        for &idx in indices {
+            self.counts[dice[idx] as usize - 1] += 1;
+        }
+        Ok(())
+    }
+}
+
+struct ManualDice;
+
+impl DiceSource for ManualDice {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        let prompt = if indices.len() == dice.len() {
+            "Enter the faces of the five dice you rolled".to_string()
+        } else {
+            let replaced: Vec<String> = indices.iter().map(|&idx| dice[idx].to_string()).collect();
+            format!("Enter the new faces for {}", replaced.join(" "))
+        };
+        let input: String = dialoguer::Input::new()
+            .with_prompt(prompt)
+            .validate_with(|input: &String| parse_faces(input, indices.len()).map(|_| ()))
+            .interact_text()
+            .unwrap();
+        let faces = parse_faces(&input, indices.len()).expect("input has been validated");
+        for (&idx, face) in indices.iter().zip(faces) {
+            dice[idx] = face;
+        }
+        Ok(())
+    }
+}
+
+// Reads the faces of the rolled dice from a file or named pipe that an
+// external process (e.g. a dice-reading camera) writes to. Every roll waits
+// for the file to be written again.
+struct FileDice {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl FileDice {
+    fn new(path: PathBuf) -> Self {
+        let last_modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Self {
+            path,
+            last_modified,
+        }
+    }
+}
+
+impl DiceSource for FileDice {
+    fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+        println!(
+            "Waiting for {} dice from {}...",
+            indices.len(),
+            self.path.display()
+        );
+        let mut last_error = None;
+        let mut reported = None;
+        loop {
+            let modified = std::fs::metadata(&self.path)
+                .and_then(|m| m.modified())
+                .ok();
+            if modified.is_some() && modified != self.last_modified {
+                let input = std::fs::read_to_string(&self.path)?;
+                match parse_faces(&input, indices.len()) {
+                    Ok(faces) => {
+                        self.last_modified = modified;
+                        for (&idx, face) in indices.iter().zip(faces) {
+                            dice[idx] = face;
+                        }
+                        return Ok(());
+                    }
+                    // the writer may not be done yet, so only report errors
+                    // that are still there on the next read
+                    Err(error) => {
+                        if last_error.as_ref() == Some(&error) && reported != last_error {
+                            println!("Cannot read dice from {}: {error}", self.path.display());
+                            reported = Some(error.clone());
+                        }
+                        last_error = Some(error);
+                    }
+                }
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
 #[derive(Debug)]
 struct DiceCounts {
     ones: u16,
@@ -92,7 +448,20 @@ impl DiceCounts {
                 return true;
             }
         }
-        return false;
+        false
+    }
+
+    fn has_two_pairs(&self) -> bool {
+        DIE_ROLLS.iter().filter(|&&die| self[die] >= 2).count() >= 2
+    }
+
+    // five dice have at most two pairs, so this is the sum of both
+    fn pairs_sum(&self) -> u16 {
+        DIE_ROLLS
+            .iter()
+            .filter(|&&die| self[die] >= 2)
+            .map(|&die| 2 * die as u16)
+            .sum()
     }
 
     fn has_fullhouse(&self) -> bool {
@@ -115,6 +484,10 @@ impl DiceCounts {
             .any(|straight| straight.iter().all(|&die| self[die] > 0))
     }
 
+    fn has_tiny_straight(&self) -> bool {
+        self.has_straight(TINY_STRAIGHTS)
+    }
+
     fn has_small_straight(&self) -> bool {
         self.has_straight(SMALL_STRAIGHTS)
     }
@@ -125,7 +498,7 @@ impl DiceCounts {
 
     fn times_die_values(&self) -> Self {
         Self {
-            ones: self.ones * 1,
+            ones: self.ones,
             twos: self.twos * 2,
             threes: self.threes * 3,
             fours: self.fours * 4,
@@ -150,23 +523,27 @@ impl Index<DieRoll> for DiceCounts {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Combination {
     Upper(DieRoll),
+    TwoPairs,
     Triple,
     Quadruple,
     Quintuple,
+    TinyStraight,
     SmallStraight,
     LargeStraight,
     Chance,
     FullHouse,
 }
 
-const LOWER_COMBINATIONS: [Combination; 7] = {
+const LOWER_COMBINATIONS: [Combination; 9] = {
     use Combination::*;
     [
+        TwoPairs,
         Triple,
         Quadruple,
+        TinyStraight,
         SmallStraight,
         LargeStraight,
         FullHouse,
@@ -175,46 +552,233 @@ const LOWER_COMBINATIONS: [Combination; 7] = {
     ]
 };
 
+impl Combination {
+    fn all() -> impl Iterator<Item = Combination> {
+        DIE_ROLLS
+            .into_iter()
+            .map(Combination::Upper)
+            .chain(LOWER_COMBINATIONS)
+    }
+
+    fn max_value(&self) -> u16 {
+        match self {
+            Combination::Upper(die) => 5 * *die as u16,
+            Combination::Triple | Combination::Quadruple | Combination::Chance => 30,
+            Combination::Quintuple => 50,
+            Combination::TwoPairs => 22,
+            Combination::TinyStraight => 20,
+            Combination::SmallStraight => 30,
+            Combination::LargeStraight => 40,
+            Combination::FullHouse => 25,
+        }
+    }
+
+    fn is_upper(&self) -> bool {
+        matches!(self, Combination::Upper(_))
+    }
+
+    // a plain name for files and the command line
+    fn key(&self) -> &'static str {
+        match self {
+            Combination::Upper(DieRoll::One) => "ones",
+            Combination::Upper(DieRoll::Two) => "twos",
+            Combination::Upper(DieRoll::Three) => "threes",
+            Combination::Upper(DieRoll::Four) => "fours",
+            Combination::Upper(DieRoll::Five) => "fives",
+            Combination::Upper(DieRoll::Six) => "sixes",
+            Combination::TwoPairs => "two-pairs",
+            Combination::Triple => "triple",
+            Combination::Quadruple => "quadruple",
+            Combination::Quintuple => "quintuple",
+            Combination::TinyStraight => "tiny-straight",
+            Combination::SmallStraight => "small-straight",
+            Combination::LargeStraight => "large-straight",
+            Combination::FullHouse => "full-house",
+            Combination::Chance => "chance",
+        }
+    }
+
+    fn shortcut(&self) -> char {
+        match self {
+            Combination::Upper(die) => char::from(b'0' + *die as u8),
+            Combination::TwoPairs => 'p',
+            Combination::Triple => 't',
+            Combination::Quadruple => 'q',
+            Combination::Quintuple => 'k',
+            Combination::TinyStraight => 'i',
+            Combination::SmallStraight => 's',
+            Combination::LargeStraight => 'l',
+            Combination::FullHouse => 'f',
+            Combination::Chance => 'c',
+        }
+    }
+}
+
+impl std::str::FromStr for Combination {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Combination::all()
+            .find(|combination| combination.key() == s)
+            .ok_or_else(|| GameError::UnknownCombination(s.to_string()))
+    }
+}
+
+// Like `Formatter::pad`, but measures the terminal width of `s` instead of
+// counting chars, so that columns line up with wide or combining glyphs.
+fn pad_to_width(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    let padding = f.width().unwrap_or(0).saturating_sub(s.width());
+    let (before, after) = match f.align() {
+        Some(std::fmt::Alignment::Right) => (padding, 0),
+        Some(std::fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+        _ => (0, padding),
+    };
+    let fill = f.fill().to_string();
+    write!(f, "{}{s}{}", fill.repeat(before), fill.repeat(after))
+}
+
+// Display names chosen with `--name`, set once the options are read.
+static NAMES: OnceLock<Vec<(Combination, String)>> = OnceLock::new();
+
+// the scorecard leaves this much room for a name
+const MAX_NAME_WIDTH: usize = 15;
+
 impl Display for Combination {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Combination::Upper(number) => f.pad(&format!("{number} s")),
-            Combination::Triple => f.pad("3 of a kind"),
-            Combination::Quadruple => f.pad("4 of a kind"),
-            Combination::Quintuple => f.pad("5 of a kind"),
-            Combination::SmallStraight => f.pad("small straight"),
-            Combination::LargeStraight => f.pad("large straight"),
-            Combination::FullHouse => f.pad("full house"),
-            Combination::Chance => f.pad("chance"),
+        let custom = NAMES
+            .get()
+            .and_then(|names| names.iter().find(|(combination, _)| combination == self));
+        if let Some((_, name)) = custom {
+            return pad_to_width(f, name);
         }
+        let upper;
+        let name = match self {
+            Combination::Upper(number) => {
+                upper = format!("{number} s");
+                &upper
+            }
+            Combination::TwoPairs => "two pairs",
+            Combination::Triple => "3 of a kind",
+            Combination::Quadruple => "4 of a kind",
+            Combination::Quintuple => "5 of a kind",
+            Combination::TinyStraight => "tiny straight",
+            Combination::SmallStraight => "small straight",
+            Combination::LargeStraight => "large straight",
+            Combination::FullHouse => "full house",
+            Combination::Chance => "chance",
+        };
+        pad_to_width(f, name)
     }
 }
 
 #[derive(Debug)]
 struct PotentialValues {
     upper: DiceCounts,
+    two_pairs: u16,
     triple: u16,
     quadruple: u16,
     quintuple: u16,
+    tiny_straight: u16,
     small_straight: u16,
     large_straight: u16,
     full_house: u16,
     chance: u16,
 }
 
+#[derive(Clone, Copy, Default)]
+enum ChanceScoring {
+    #[default]
+    Sum,
+    Capped(u16),
+    Fixed(u16),
+}
+
+impl std::str::FromStr for ChanceScoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let amount = |value: &str| {
+            value
+                .parse()
+                .map_err(|_| format!("invalid chance value: {value}"))
+        };
+        match s.split_once(':') {
+            None if s == "sum" => Ok(ChanceScoring::Sum),
+            Some(("capped", max)) => Ok(ChanceScoring::Capped(amount(max)?)),
+            Some(("fixed", value)) => Ok(ChanceScoring::Fixed(amount(value)?)),
+            _ => Err(format!("unknown chance scoring: {s}")),
+        }
+    }
+}
+
+impl Display for ChanceScoring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChanceScoring::Sum => write!(f, "sum"),
+            ChanceScoring::Capped(max) => write!(f, "capped:{max}"),
+            ChanceScoring::Fixed(value) => write!(f, "fixed:{value}"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct ScoringTable {
+    full_house_as_sum: bool,
+    chance: ChanceScoring,
+}
+
+impl ScoringTable {
+    fn max_value(&self, combination: Combination) -> u16 {
+        match combination {
+            Combination::FullHouse if self.full_house_as_sum => 28,
+            Combination::Chance => match self.chance {
+                ChanceScoring::Sum => 30,
+                ChanceScoring::Capped(max) => max.min(30),
+                ChanceScoring::Fixed(value) => value,
+            },
+            _ => combination.max_value(),
+        }
+    }
+}
+
 impl PotentialValues {
-    fn new(counts: DiceCounts) -> Self {
+    fn new(counts: DiceCounts, scoring: &ScoringTable) -> Self {
+        let full_house = if scoring.full_house_as_sum {
+            counts.sum()
+        } else {
+            25
+        };
         Self {
             upper: counts.times_die_values(),
+            two_pairs: if counts.has_two_pairs() {
+                counts.pairs_sum()
+            } else {
+                0
+            },
             triple: if counts.has_tuple(3) { counts.sum() } else { 0 },
             quadruple: if counts.has_tuple(4) { counts.sum() } else { 0 },
             quintuple: if counts.has_tuple(5) { 50 } else { 0 },
-            chance: counts.sum(),
+            chance: match scoring.chance {
+                ChanceScoring::Sum => counts.sum(),
+                ChanceScoring::Capped(max) => counts.sum().min(max),
+                ChanceScoring::Fixed(value) => value,
+            },
+            tiny_straight: if counts.has_tiny_straight() { 20 } else { 0 },
             small_straight: if counts.has_small_straight() { 30 } else { 0 },
             large_straight: if counts.has_large_straight() { 40 } else { 0 },
-            full_house: if counts.has_fullhouse() { 25 } else { 0 },
+            full_house: if counts.has_fullhouse() {
+                full_house
+            } else {
+                0
+            },
         }
     }
+
+    fn best(&self, combinations: impl Iterator<Item = Combination>) -> Option<(Combination, u16)> {
+        combinations
+            .map(|combination| (combination, self[combination]))
+            .min_by_key(|&(_, value)| std::cmp::Reverse(value))
+    }
 }
 
 impl Index<Combination> for PotentialValues {
@@ -223,9 +787,11 @@ impl Index<Combination> for PotentialValues {
     fn index(&self, index: Combination) -> &Self::Output {
         match index {
             Combination::Upper(number) => &self.upper[number],
+            Combination::TwoPairs => &self.two_pairs,
             Combination::Triple => &self.triple,
             Combination::Quadruple => &self.quadruple,
             Combination::Quintuple => &self.quintuple,
+            Combination::TinyStraight => &self.tiny_straight,
             Combination::SmallStraight => &self.small_straight,
             Combination::LargeStraight => &self.large_straight,
             Combination::Chance => &self.chance,
@@ -234,193 +800,4106 @@ impl Index<Combination> for PotentialValues {
     }
 }
 
-#[derive(Clone, Copy)]
-struct ValuedCombination {
-    combination: Combination,
-    value: u16,
+fn reroll_outcomes(n: usize) -> Vec<(Vec<DieRoll>, f64)> {
+    fn extend(
+        outcome: &mut Vec<DieRoll>,
+        first_face: usize,
+        n: usize,
+        outcomes: &mut Vec<(Vec<DieRoll>, f64)>,
+    ) {
+        if outcome.len() == n {
+            let mut permutations = (1..=n).product::<usize>();
+            for die in DIE_ROLLS {
+                let count = outcome.iter().filter(|&&d| d == die).count();
+                permutations /= (1..=count).product::<usize>();
+            }
+            let probability = permutations as f64 / 6f64.powi(n as i32);
+            outcomes.push((outcome.clone(), probability));
+            return;
+        }
+        for (face, &die) in DIE_ROLLS.iter().enumerate().skip(first_face) {
+            outcome.push(die);
+            extend(outcome, face, n, outcomes);
+            outcome.pop();
+        }
+    }
+
+    let mut outcomes = Vec::new();
+    extend(&mut Vec::with_capacity(n), 0, n, &mut outcomes);
+    outcomes
 }
 
-impl Display for ValuedCombination {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let ValuedCombination { combination, value } = self;
-        write!(f, "{value:2} for {combination:10}")
+fn completes(target: Combination, dice: DiceRolls) -> bool {
+    let counts = DiceCounts::new(dice);
+    match target {
+        // an upper box counts as done with three of its face, which is what
+        // the bonus threshold of 63 asks for on average
+        Combination::Upper(die) => counts[die] >= 3,
+        // whether a combination scores at all does not depend on the table
+        _ => PotentialValues::new(counts, &ScoringTable::default())[target] > 0,
     }
 }
 
-struct Score {
-    upper: u16,
-    lower: u16,
-    bonus: u16,
+struct TargetOdds {
+    target: Combination,
+    outcomes: Vec<Vec<(Vec<DieRoll>, f64)>>,
+    memo: HashMap<(DiceRolls, u8), f64>,
+    keeps: HashMap<(DiceRolls, u8), (Vec<usize>, f64)>,
 }
 
-impl Score {
-    fn total(&self) -> u16 {
-        self.upper + self.lower + self.bonus
+impl TargetOdds {
+    fn new(target: Combination) -> Self {
+        Self {
+            target,
+            outcomes: (0..=5).map(reroll_outcomes).collect(),
+            memo: HashMap::new(),
+            keeps: HashMap::new(),
+        }
     }
-}
 
-impl Display for Score {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Score {
-            upper,
-            lower,
-            bonus,
-        } = self;
-        let total = self.total();
-        write!(
-            f,
-            "Upper: {upper:3} Bonus: {bonus:2} Lower: {lower:3} Total: {total:3}"
-        )
+    fn keep_probability(&mut self, dice: DiceRolls, keep: &[usize], rerolls_left: u8) -> f64 {
+        let rerolled = 5 - keep.len();
+        let mut probability = 0.0;
+        for o in 0..self.outcomes[rerolled].len() {
+            let (faces, p) = &self.outcomes[rerolled][o];
+            let mut faces = faces.iter();
+            let next: DiceRolls = std::array::from_fn(|idx| {
+                if keep.contains(&idx) {
+                    dice[idx]
+                } else {
+                    *faces.next().unwrap()
+                }
+            });
+            let p = *p;
+            probability += p * self.probability(next, rerolls_left - 1);
+        }
+        probability
+    }
+
+    fn best_keep(&mut self, dice: DiceRolls, rerolls_left: u8) -> (Vec<usize>, f64) {
+        let mut best = ((0..5).collect(), self.probability(dice, 0));
+        if rerolls_left == 0 {
+            return best;
+        }
+        if let Some(best) = self.keeps.get(&(dice, rerolls_left)) {
+            return best.clone();
+        }
+        // larger keeps first, so that ties favor rerolling fewer dice
+        for mask in (0..31u8).rev() {
+            let keep: Vec<usize> = (0..5).filter(|idx| mask & (1 << idx) != 0).collect();
+            let probability = self.keep_probability(dice, &keep, rerolls_left);
+            if probability > best.1 + 1e-9 {
+                best = (keep, probability);
+            }
+        }
+        self.keeps.insert((dice, rerolls_left), best.clone());
+        best
+    }
+
+    fn probability(&mut self, mut dice: DiceRolls, rerolls_left: u8) -> f64 {
+        dice.sort();
+        if rerolls_left == 0 {
+            return if completes(self.target, dice) {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        if let Some(&probability) = self.memo.get(&(dice, rerolls_left)) {
+            return probability;
+        }
+        let (_, probability) = self.best_keep(dice, rerolls_left);
+        self.memo.insert((dice, rerolls_left), probability);
+        probability
     }
 }
 
-struct PlayerState {
-    filled: Vec<ValuedCombination>,
+fn best_keep_for(target: Combination, dice: DiceRolls, rerolls_left: u8) -> (Vec<usize>, f64) {
+    TargetOdds::new(target).best_keep(dice, rerolls_left)
 }
 
-impl PlayerState {
-    fn new() -> Self {
-        PlayerState { filled: Vec::new() }
+fn chance_ev(kept: &[DieRoll], rerolls_left: u8) -> f64 {
+    // with n rolls left, a die is rolled again whenever it shows less than
+    // what n - 1 rolls are expected to bring
+    let mut die_ev = 0.0;
+    for _ in 0..rerolls_left {
+        die_ev = DIE_ROLLS
+            .iter()
+            .map(|&die| (die as u16 as f64).max(die_ev))
+            .sum::<f64>()
+            / 6.0;
     }
+    let kept_sum: u16 = kept.iter().map(|&die| die as u16).sum();
+    kept_sum as f64 + (5 - kept.len()) as f64 * die_ev
+}
 
-    fn has_combination(&self, combination: Combination) -> bool {
-        self.filled.iter().any(|vc| vc.combination == combination)
+fn best_chance_keep(dice: DiceRolls, rerolls_left: u8) -> (Vec<usize>, f64) {
+    let mut best = (Vec::new(), f64::MIN);
+    for mask in (0..32u8).rev() {
+        let keep: Vec<usize> = (0..5).filter(|idx| mask & (1 << idx) != 0).collect();
+        let kept: Vec<DieRoll> = keep.iter().map(|&idx| dice[idx]).collect();
+        let ev = chance_ev(&kept, rerolls_left);
+        if ev > best.1 + 1e-9 {
+            best = (keep, ev);
+        }
     }
+    best
+}
 
-    fn record_value(&mut self, vc: ValuedCombination) -> Result<(), &'static str> {
-        if self.has_combination(vc.combination) {
-            return Err("combination already recorded");
-        } else {
-            self.filled.push(vc);
-            Ok(())
+// Exact expected value of the rest of the game, which is only tractable once
+// few boxes are left. Open boxes are a bit set over `Rules::combinations`,
+// and the upper section only matters as far as the bonus is still open.
+struct Endgame {
+    combinations: Vec<Combination>,
+    // the first roll restrictions of forced upper are not modeled
+    solvable: bool,
+    rerolls: u8,
+    bonus: bool,
+    scoring: ScoringTable,
+    outcomes: Vec<Vec<(Vec<DieRoll>, f64)>>,
+    turns: HashMap<(u16, u16), f64>,
+    rolls: HashMap<(DiceRolls, u8, u16, u16), f64>,
+}
+
+impl Endgame {
+    const MAX_OPEN: usize = 3;
+
+    fn new(rules: &Rules) -> Self {
+        Self {
+            combinations: rules.combinations().collect(),
+            solvable: !rules.forced_upper,
+            rerolls: rules.rerolls,
+            bonus: rules.bonus == BonusMode::Standard,
+            scoring: rules.scoring.clone(),
+            outcomes: (0..=5).map(reroll_outcomes).collect(),
+            turns: HashMap::new(),
+            rolls: HashMap::new(),
         }
     }
 
-    fn score(&self) -> Score {
-        let mut upper = 0;
-        let mut lower = 0;
-        for ValuedCombination { combination, value } in &self.filled {
-            match combination {
-                Combination::Upper(_) => upper += value,
-                _ => lower += value,
+    // `None` if there are too many open boxes to solve exactly
+    fn state(&self, player_state: &PlayerState) -> Option<(u16, u16)> {
+        if !self.solvable || player_state.columns != 1 {
+            return None;
+        }
+        let mut open: u16 = 0;
+        for (bit, &combination) in self.combinations.iter().enumerate() {
+            if !player_state.has_combination(combination) {
+                open |= 1 << bit;
             }
         }
+        if open == 0 || open.count_ones() as usize > Self::MAX_OPEN {
+            return None;
+        }
+        Some(self.normalize(open, player_state.upper_progress(0)))
+    }
 
-        let bonus = if upper >= 63 { 35 } else { 0 };
+    fn normalize(&self, open: u16, upper: u16) -> (u16, u16) {
+        let upper_open = self
+            .combinations
+            .iter()
+            .enumerate()
+            .any(|(bit, c)| open & (1 << bit) != 0 && c.is_upper());
+        if !self.bonus || !upper_open {
+            (open, 0)
+        } else {
+            (open, upper.min(BONUS_THRESHOLD))
+        }
+    }
 
-        Score {
-            upper,
-            lower,
-            bonus,
+    fn expected(&mut self, open: u16, upper: u16) -> f64 {
+        if open == 0 {
+            return 0.0;
+        }
+        if let Some(&expected) = self.turns.get(&(open, upper)) {
+            return expected;
+        }
+        let mut expected = 0.0;
+        for o in 0..self.outcomes[5].len() {
+            let (faces, p) = &self.outcomes[5][o];
+            let dice: DiceRolls = faces.clone().try_into().unwrap();
+            let p = *p;
+            expected += p * self.roll_value(dice, self.rerolls, open, upper);
         }
+        self.turns.insert((open, upper), expected);
+        expected
     }
 
-    fn is_done(&self) -> bool {
-        self.filled.len() >= DIE_ROLLS.len() + LOWER_COMBINATIONS.len()
+    fn roll_value(&mut self, mut dice: DiceRolls, rerolls_left: u8, open: u16, upper: u16) -> f64 {
+        dice.sort();
+        if rerolls_left == 0 {
+            return self.best_record(dice, open, upper).1;
+        }
+        if let Some(&value) = self.rolls.get(&(dice, rerolls_left, open, upper)) {
+            return value;
+        }
+        let (_, value) = self.best_keep(dice, rerolls_left, open, upper);
+        self.rolls.insert((dice, rerolls_left, open, upper), value);
+        value
     }
 
-    fn display(&self, term: &console::Term) -> std::io::Result<()> {
-        for die in DIE_ROLLS {
-            let combination = Combination::Upper(die);
-            if let Some(ValuedCombination { combination, value }) =
-                self.filled.iter().find(|vc| vc.combination == combination)
-            {
-                println!(
-                    "{:15} ({value:2})",
-                    console::style(combination).strikethrough()
-                );
-            } else {
-                println!("{combination:15}     ");
-            }
+    fn best_keep(
+        &mut self,
+        dice: DiceRolls,
+        rerolls_left: u8,
+        open: u16,
+        upper: u16,
+    ) -> (Vec<usize>, f64) {
+        let mut best = ((0..5).collect(), self.roll_value(dice, 0, open, upper));
+        if rerolls_left == 0 {
+            return best;
         }
-        for (i, &combination) in LOWER_COMBINATIONS.iter().enumerate() {
-            term.move_cursor_to(23, i)?;
-            if let Some(ValuedCombination { combination, value }) =
-                self.filled.iter().find(|vc| vc.combination == combination)
-            {
-                println!(
-                    "{:15} ({value:2})",
-                    console::style(combination).strikethrough()
-                );
+        let mut tried = Vec::new();
+        for mask in (0..31u8).rev() {
+            let keep: Vec<usize> = (0..5).filter(|idx| mask & (1 << idx) != 0).collect();
+            let kept: Vec<DieRoll> = keep.iter().map(|&idx| dice[idx]).collect();
+            // the dice are sorted, so equal faces kept give equal keeps
+            if tried.contains(&kept) {
+                continue;
+            }
+            let value = self.keep_value(dice, &keep, rerolls_left, open, upper);
+            tried.push(kept);
+            if value > best.1 + 1e-9 {
+                best = (keep, value);
+            }
+        }
+        best
+    }
+
+    // the points to expect when keeping the given dice and rolling the others
+    fn keep_value(
+        &mut self,
+        dice: DiceRolls,
+        keep: &[usize],
+        rerolls_left: u8,
+        open: u16,
+        upper: u16,
+    ) -> f64 {
+        if keep.len() == dice.len() || rerolls_left == 0 {
+            return self.roll_value(dice, 0, open, upper);
+        }
+        let kept: Vec<DieRoll> = keep.iter().map(|&idx| dice[idx]).collect();
+        let mut value = 0.0;
+        for o in 0..self.outcomes[5 - kept.len()].len() {
+            let (faces, p) = &self.outcomes[5 - kept.len()][o];
+            let mut next = kept.clone();
+            next.extend(faces);
+            let next: DiceRolls = next.try_into().unwrap();
+            let p = *p;
+            value += p * self.roll_value(next, rerolls_left - 1, open, upper);
+        }
+        value
+    }
+
+    fn best_record(&mut self, dice: DiceRolls, open: u16, upper: u16) -> (Combination, f64) {
+        self.record_values(dice, open, upper)
+            .into_iter()
+            .reduce(|best, next| if next.1 > best.1 { next } else { best })
+            .expect("there is always an open box to record")
+    }
+
+    // the points to expect for the rest of the game after recording the dice
+    // in each of the open boxes
+    fn record_values(&mut self, dice: DiceRolls, open: u16, upper: u16) -> Vec<(Combination, f64)> {
+        let values = PotentialValues::new(DiceCounts::new(dice), &self.scoring);
+        let mut record_values = Vec::new();
+        for bit in 0..self.combinations.len() {
+            if open & (1 << bit) == 0 {
+                continue;
+            }
+            let combination = self.combinations[bit];
+            let value = values[combination];
+            let mut next_upper = upper;
+            let mut gained = value as f64;
+            if combination.is_upper() {
+                next_upper += value;
+                if self.bonus && upper < BONUS_THRESHOLD && next_upper >= BONUS_THRESHOLD {
+                    gained += 35.0;
+                }
+            }
+            let (rest, rest_upper) = self.normalize(open & !(1 << bit), next_upper);
+            record_values.push((combination, gained + self.expected(rest, rest_upper)));
+        }
+        record_values
+    }
+}
+
+#[derive(Clone)]
+struct ValuedCombination {
+    combination: Combination,
+    column: usize,
+    value: u16,
+    note: Option<String>,
+    natural: bool,
+    // the dice that were recorded, to score them again under other rules
+    dice: DiceRolls,
+}
+
+// Upper boxes and chance can always be scored, so only the other lower
+// combinations can be rolled outright.
+fn is_natural(combination: Combination, first_roll: DiceRolls) -> bool {
+    match combination {
+        Combination::Upper(_) | Combination::Chance => false,
+        _ => {
+            let counts = DiceCounts::new(first_roll);
+            PotentialValues::new(counts, &ScoringTable::default())[combination] > 0
+        }
+    }
+}
+
+impl Display for ValuedCombination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ValuedCombination {
+            combination, value, ..
+        } = self;
+        write!(f, "{value:2} for {combination:10}")
+    }
+}
+
+struct Score {
+    upper: u16,
+    lower: u16,
+    bonus: u16,
+}
+
+impl Score {
+    fn total(&self) -> u16 {
+        self.upper + self.lower + self.bonus
+    }
+
+    fn compact(&self) -> String {
+        let Score {
+            upper,
+            lower,
+            bonus,
+        } = self;
+        format!("U{upper} B{bonus} L{lower} = {}", self.total())
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Score {
+            upper,
+            lower,
+            bonus,
+        } = self;
+        let total = self.total();
+        write!(
+            f,
+            "Upper: {upper:3} Bonus: {bonus:2} Lower: {lower:3} Total: {total:3}"
+        )
+    }
+}
+
+const BONUS_THRESHOLD: u16 = 63;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum BonusMode {
+    #[default]
+    Standard,
+    None,
+}
+
+struct Rules {
+    rerolls: u8,
+    forced_upper: bool,
+    tiny_straight: bool,
+    two_pairs: bool,
+    upper_only: bool,
+    must_improve: bool,
+    bonus: BonusMode,
+    scoring: ScoringTable,
+    columns: usize,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            rerolls: 2,
+            forced_upper: false,
+            tiny_straight: false,
+            two_pairs: false,
+            upper_only: false,
+            must_improve: false,
+            bonus: BonusMode::Standard,
+            scoring: ScoringTable::default(),
+            columns: 1,
+        }
+    }
+}
+
+impl Rules {
+    fn is_enabled(&self, combination: Combination) -> bool {
+        match combination {
+            _ if self.upper_only => combination.is_upper(),
+            Combination::TinyStraight => self.tiny_straight,
+            Combination::TwoPairs => self.two_pairs,
+            _ => true,
+        }
+    }
+
+    fn combinations(&self) -> impl Iterator<Item = Combination> + '_ {
+        Combination::all().filter(|&combination| self.is_enabled(combination))
+    }
+
+    fn total_boxes(&self) -> usize {
+        self.combinations().count()
+    }
+
+    fn filter_recordable(&self, valued_combinations: &mut Vec<ValuedCombination>, rolls: u8) {
+        let upper_open = valued_combinations
+            .iter()
+            .any(|vc| vc.combination.is_upper());
+        if self.forced_upper && rolls == 1 && upper_open {
+            valued_combinations.retain(|vc| vc.combination.is_upper());
+        }
+    }
+
+    // the options of a `--config` file that `config_lines` writes
+    const CONFIG_OPTIONS: [&'static str; 10] = [
+        "no-rerolls",
+        "forced-upper",
+        "tiny-straight",
+        "two-pairs",
+        "upper-only",
+        "must-improve",
+        "no-bonus",
+        "full-house-sum",
+        "chance",
+        "columns",
+    ];
+
+    // the rules as lines of a `--config` file
+    fn config_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let flags = [
+            (self.rerolls == 0, "no-rerolls"),
+            (self.forced_upper, "forced-upper"),
+            (self.tiny_straight, "tiny-straight"),
+            (self.two_pairs, "two-pairs"),
+            (self.upper_only, "upper-only"),
+            (self.must_improve, "must-improve"),
+            (self.bonus == BonusMode::None, "no-bonus"),
+            (self.scoring.full_house_as_sum, "full-house-sum"),
+        ];
+        for (on, flag) in flags {
+            if on {
+                lines.push(flag.to_string());
+            }
+        }
+        if !matches!(self.scoring.chance, ChanceScoring::Sum) {
+            lines.push(format!("chance {}", self.scoring.chance));
+        }
+        if self.columns > 1 {
+            lines.push(format!("columns {}", self.columns));
+        }
+        lines
+    }
+}
+
+fn edit_rules(mut rules: Rules, path: &Path) -> std::io::Result<()> {
+    let positive = |value: &u16| {
+        if *value > 0 {
+            Ok(())
+        } else {
+            Err("must be positive")
+        }
+    };
+    loop {
+        let on = |on: bool| if on { "on" } else { "off" };
+        let items = [
+            format!("rerolls: {}", on(rules.rerolls > 0)),
+            format!("forced upper: {}", on(rules.forced_upper)),
+            format!("tiny straight: {}", on(rules.tiny_straight)),
+            format!("two pairs: {}", on(rules.two_pairs)),
+            format!("upper only: {}", on(rules.upper_only)),
+            format!("must improve: {}", on(rules.must_improve)),
+            format!("bonus: {}", on(rules.bonus == BonusMode::Standard)),
+            format!("full house as sum: {}", on(rules.scoring.full_house_as_sum)),
+            format!("chance: {}", rules.scoring.chance),
+            format!("columns: {}", rules.columns),
+            format!("save to {}", path.display()),
+            "quit without saving".to_string(),
+        ];
+        let selection = dialoguer::Select::new()
+            .with_prompt("Which rule do you want to change?")
+            .items(&items)
+            .default(0)
+            .interact()
+            .unwrap();
+        match selection {
+            0 => rules.rerolls = if rules.rerolls > 0 { 0 } else { 2 },
+            1 => rules.forced_upper = !rules.forced_upper,
+            2 => rules.tiny_straight = !rules.tiny_straight,
+            3 => rules.two_pairs = !rules.two_pairs,
+            4 => rules.upper_only = !rules.upper_only,
+            5 => rules.must_improve = !rules.must_improve,
+            6 => {
+                rules.bonus = match rules.bonus {
+                    BonusMode::Standard => BonusMode::None,
+                    BonusMode::None => BonusMode::Standard,
+                }
+            }
+            7 => rules.scoring.full_house_as_sum = !rules.scoring.full_house_as_sum,
+            8 => {
+                let kind = dialoguer::Select::new()
+                    .with_prompt("How should chance be scored?")
+                    .items(&["sum of the dice", "sum, but capped", "fixed value"])
+                    .default(0)
+                    .interact()
+                    .unwrap();
+                rules.scoring.chance = match kind {
+                    0 => ChanceScoring::Sum,
+                    _ => {
+                        let value = dialoguer::Input::new()
+                            .with_prompt(if kind == 1 { "Cap" } else { "Value" })
+                            .default(25)
+                            .validate_with(positive)
+                            .interact_text()
+                            .unwrap();
+                        if kind == 1 {
+                            ChanceScoring::Capped(value)
+                        } else {
+                            ChanceScoring::Fixed(value)
+                        }
+                    }
+                };
+            }
+            9 => {
+                let columns: u16 = dialoguer::Input::new()
+                    .with_prompt("Columns")
+                    .default(rules.columns as u16)
+                    .validate_with(positive)
+                    .interact_text()
+                    .unwrap();
+                rules.columns = columns.into();
+            }
+            10 => {
+                // other options in the file stay, the rules are replaced
+                let mut lines: Vec<String> = match std::fs::read_to_string(path) {
+                    Ok(content) => content
+                        .lines()
+                        .filter(|line| {
+                            let name = line.split_whitespace().next().unwrap_or("");
+                            !Rules::CONFIG_OPTIONS.contains(&name.trim_start_matches('-'))
+                        })
+                        .map(str::to_string)
+                        .collect(),
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                    Err(error) => return Err(error),
+                };
+                lines.extend(rules.config_lines());
+                let mut content = lines.join("\n");
+                content.push('\n');
+                std::fs::write(path, content)?;
+                println!("Saved, play with --config {}", path.display());
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+// Every combination is recorded once per column. Columns are numbered from
+// zero and all of them are scored like a separate scorecard.
+#[derive(Clone)]
+struct PlayerState {
+    columns: usize,
+    filled: Vec<ValuedCombination>,
+}
+
+impl PlayerState {
+    fn new(columns: usize) -> Self {
+        PlayerState {
+            columns,
+            filled: Vec::new(),
+        }
+    }
+
+    fn find(&self, column: usize, combination: Combination) -> Option<&ValuedCombination> {
+        self.filled
+            .iter()
+            .find(|vc| vc.column == column && vc.combination == combination)
+    }
+
+    fn open_columns(&self, combination: Combination) -> Vec<usize> {
+        (0..self.columns)
+            .filter(|&column| self.find(column, combination).is_none())
+            .collect()
+    }
+
+    fn has_combination(&self, combination: Combination) -> bool {
+        self.open_columns(combination).is_empty()
+    }
+
+    fn open_combinations<'a>(&'a self, rules: &'a Rules) -> impl Iterator<Item = Combination> + 'a {
+        rules
+            .combinations()
+            .filter(|&combination| !self.has_combination(combination))
+    }
+
+    // what the dice score in every open box, best first
+    fn ranked_combinations(&self, rules: &Rules, dice: DiceRolls) -> Vec<ValuedCombination> {
+        let values = PotentialValues::new(DiceCounts::new(dice), &rules.scoring);
+        let mut valued_combinations: Vec<ValuedCombination> = self
+            .open_combinations(rules)
+            .map(|combination| ValuedCombination {
+                combination,
+                column: 0,
+                value: values[combination],
+                note: None,
+                natural: false,
+                dice,
+            })
+            .collect();
+        valued_combinations.sort_by_key(|vc| std::cmp::Reverse(vc.value));
+        valued_combinations
+    }
+
+    fn record_value(&mut self, vc: ValuedCombination) -> Result<(), GameError> {
+        if self.find(vc.column, vc.combination).is_some() {
+            Err(GameError::AlreadyRecorded(vc.combination))
+        } else {
+            self.filled.push(vc);
+            Ok(())
+        }
+    }
+
+    fn by_section(&self) -> (Vec<&ValuedCombination>, Vec<&ValuedCombination>) {
+        self.filled.iter().partition(|vc| vc.combination.is_upper())
+    }
+
+    // every upper box holds five of its face, which is the most it can score
+    fn perfect_upper(&self, column: usize) -> bool {
+        let (upper, _) = self.by_section();
+        DIE_ROLLS.iter().all(|&die| {
+            let combination = Combination::Upper(die);
+            upper.iter().any(|vc| {
+                vc.column == column
+                    && vc.combination == combination
+                    && vc.value == combination.max_value()
+            })
+        })
+    }
+
+    fn upper_progress(&self, column: usize) -> u16 {
+        self.by_section()
+            .0
+            .iter()
+            .filter(|vc| vc.column == column)
+            .map(|vc| vc.value)
+            .sum()
+    }
+
+    // the chance of the bonus in a column, also once it is certain
+    fn bonus_odds(&self, rules: &Rules, column: usize) -> f64 {
+        if rules.bonus == BonusMode::None {
+            0.0
+        } else if self.upper_progress(column) >= BONUS_THRESHOLD {
+            1.0
+        } else {
+            self.bonus_chance(rules, column).unwrap_or(0.0)
+        }
+    }
+
+    // the bonus points expected to be gained by recording `vc`
+    fn bonus_gain(&self, rules: &Rules, vc: &ValuedCombination) -> f64 {
+        let mut with_vc = self.clone();
+        with_vc.filled.push(vc.clone());
+        35.0 * (with_vc.bonus_odds(rules, vc.column) - self.bonus_odds(rules, vc.column))
+    }
+
+    // Models every open upper box as being filled by a turn spent chasing
+    // the most frequent face, which is how the bonus is usually played for.
+    fn bonus_chance(&self, rules: &Rules, column: usize) -> Option<f64> {
+        if rules.bonus == BonusMode::None {
+            return None;
+        }
+        let needed = BONUS_THRESHOLD.saturating_sub(self.upper_progress(column)) as usize;
+        let open: Vec<DieRoll> = DIE_ROLLS
+            .into_iter()
+            .filter(|&die| self.find(column, Combination::Upper(die)).is_none())
+            .collect();
+        if needed == 0 || open.is_empty() {
+            return None;
+        }
+        let mut count_odds = [0.0; 6];
+        for (faces, p) in reroll_outcomes(5) {
+            let counts = DiceCounts::new(faces.try_into().unwrap());
+            let most = DIE_ROLLS.iter().map(|&die| counts[die]).max().unwrap();
+            count_odds[most as usize] += p;
+        }
+        for _ in 0..rules.rerolls {
+            let mut next = [0.0; 6];
+            for (count, p) in count_odds.iter().enumerate() {
+                for (hits, q) in reroll_outcomes(5 - count) {
+                    // the odds are the same for every face, so count ones
+                    let hits = hits.iter().filter(|&&die| die == DieRoll::One).count();
+                    next[count + hits] += p * q;
+                }
+            }
+            count_odds = next;
+        }
+        let mut sum_odds = vec![0.0; 5 * 21 + 1];
+        sum_odds[0] = 1.0;
+        for die in open {
+            let mut next = vec![0.0; sum_odds.len()];
+            for (sum, p) in sum_odds.iter().enumerate().filter(|(_, &p)| p > 0.0) {
+                for (count, q) in count_odds.iter().enumerate() {
+                    next[sum + count * die as usize] += p * q;
+                }
+            }
+            sum_odds = next;
+        }
+        Some(sum_odds.iter().skip(needed).sum())
+    }
+
+    fn score(&self, rules: &Rules) -> Score {
+        let mut score = Score {
+            upper: 0,
+            lower: 0,
+            bonus: 0,
+        };
+        for column in 0..self.columns {
+            let (upper, lower) = self.by_section();
+            let upper: u16 = upper
+                .iter()
+                .filter(|vc| vc.column == column)
+                .map(|vc| vc.value)
+                .sum();
+            let lower: u16 = lower
+                .iter()
+                .filter(|vc| vc.column == column)
+                .map(|vc| vc.value)
+                .sum();
+
+            let bonus = match rules.bonus {
+                BonusMode::Standard if upper >= BONUS_THRESHOLD => 35,
+                BonusMode::Standard | BonusMode::None => 0,
+            };
+
+            score.upper += upper;
+            score.lower += lower;
+            score.bonus += bonus;
+        }
+        score
+    }
+
+    fn is_done(&self, rules: &Rules) -> bool {
+        self.filled.len() >= rules.total_boxes() * self.columns
+    }
+
+    // what the last record added to the total, including a bonus it earned
+    fn last_gain(&self, rules: &Rules) -> Option<u16> {
+        let (_, before) = self.filled.split_last()?;
+        let before = PlayerState {
+            columns: self.columns,
+            filled: before.to_vec(),
+        };
+        Some(self.score(rules).total() - before.score(rules).total())
+    }
+
+    fn display(
+        &self,
+        rules: &Rules,
+        palette: Palette,
+        show_gain: bool,
+        strike_zeros: bool,
+    ) -> std::io::Result<()> {
+        let marker_width = palette.marker(0).width();
+        // with a single column the name shows whether it scored, otherwise
+        // every value does so for its own column
+        let row = |combination: Combination| match self.find(0, combination) {
+            Some(&ValuedCombination { value, .. }) if self.columns == 1 => format!(
+                "{:15} ({value:2}){}",
+                palette.recorded(combination, value, strike_zeros),
+                palette.marker(value)
+            ),
+            _ => {
+                let mut row = format!("{combination:15}");
+                for column in 0..self.columns {
+                    if let Some(&ValuedCombination { value, .. }) = self.find(column, combination) {
+                        row += &format!(
+                            " ({:2}){}",
+                            palette.recorded(value, value, strike_zeros),
+                            palette.marker(value)
+                        );
+                    } else {
+                        row += &" ".repeat(5 + marker_width);
+                    }
+                }
+                row
+            }
+        };
+        let mut upper: Vec<String> = DIE_ROLLS
+            .into_iter()
+            .map(|die| row(Combination::Upper(die)))
+            .collect();
+        if rules.bonus == BonusMode::Standard && self.columns == 1 {
+            upper.push(palette.progress_bar(self.upper_progress(0)).to_string());
+        }
+        let lower: Vec<String> = rules
+            .combinations()
+            .filter(|c| !c.is_upper())
+            .map(row)
+            .collect();
+        // the upper rows are one marker narrower than they are printed, as
+        // the lower section may start right after the last marker
+        let lower_column = 18 + self.columns * (5 + marker_width) - marker_width;
+        // the sections are put side by side line by line rather than with
+        // cursor moves, so that the board can be printed anywhere
+        for i in 0..upper.len().max(lower.len()) {
+            let left = upper.get(i).map_or("", String::as_str);
+            match lower.get(i) {
+                Some(right) => println!(
+                    "{}{right}",
+                    console::pad_str(left, lower_column, console::Alignment::Left, None)
+                ),
+                None => println!("{left}"),
+            }
+        }
+        println!();
+        match self.last_gain(rules) {
+            Some(gain) if show_gain => println!("{}  (+{gain} this turn)", self.score(rules)),
+            _ => println!("{}", self.score(rules)),
+        }
+        let chances: Vec<String> = (0..self.columns)
+            .filter_map(|column| self.bonus_chance(rules, column))
+            .map(|chance| format!("~{:.0}%", chance * 100.0))
+            .collect();
+        if !chances.is_empty() {
+            println!("Bonus chance: {}", chances.join(" "));
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, rules: &Rules) -> String {
+        let mut snapshot = String::new();
+        for combination in rules.combinations() {
+            snapshot += &format!("{combination:15}");
+            for column in 0..self.columns {
+                match self.find(column, combination) {
+                    Some(vc) => snapshot += &format!(" ({:2})", vc.value),
+                    None => snapshot += "     ",
+                }
+            }
+            snapshot.truncate(snapshot.trim_end().len());
+            snapshot += "\n";
+        }
+        snapshot + &format!("{}\n\n", self.score(rules))
+    }
+}
+
+// Scores a finished scorecard given the dice that went into every box, e.g.
+// to check a strategy without playing it out interactively.
+fn evaluate_scorecard(
+    assignments: &[(Combination, DiceRolls)],
+    rules: &Rules,
+) -> Result<Score, GameError> {
+    let player_state = load_scorecard(assignments, rules)?;
+    if let Some(combination) = player_state.open_combinations(rules).next() {
+        return Err(GameError::MissingCombination(combination));
+    }
+    Ok(player_state.score(rules))
+}
+
+// With several columns, a box named again goes into the next column.
+fn load_scorecard(
+    assignments: &[(Combination, DiceRolls)],
+    rules: &Rules,
+) -> Result<PlayerState, GameError> {
+    let mut player_state = PlayerState::new(rules.columns);
+    for &(combination, dice) in assignments {
+        if !rules.is_enabled(combination) {
+            return Err(GameError::DisabledCombination(combination));
+        }
+        let values = PotentialValues::new(DiceCounts::new(dice), &rules.scoring);
+        let column = *player_state
+            .open_columns(combination)
+            .first()
+            .ok_or(GameError::AlreadyRecorded(combination))?;
+        player_state.record_value(ValuedCombination {
+            combination,
+            column,
+            value: values[combination],
+            note: None,
+            natural: false,
+            dice,
+        })?;
+    }
+    Ok(player_state)
+}
+
+fn compare_scorecards(a: &PlayerState, b: &PlayerState, rules: &Rules, palette: Palette) {
+    let cell = |value: Option<u16>| {
+        value
+            .map(|value| format!("{value:5}"))
+            .unwrap_or(" ".repeat(5))
+    };
+    let diff = |a: Option<u16>, b: Option<u16>| match (a, b) {
+        (Some(a), Some(b)) if a != b => palette.difference(i32::from(b) - i32::from(a)),
+        _ => String::new(),
+    };
+    println!("{:15} {:>5} {:>5}", "", "A", "B");
+    for column in 0..rules.columns {
+        if rules.columns > 1 {
+            println!("column {}", column + 1);
+        }
+        for combination in rules.combinations() {
+            let a = a.find(column, combination).map(|vc| vc.value);
+            let b = b.find(column, combination).map(|vc| vc.value);
+            println!("{combination:15} {} {}{}", cell(a), cell(b), diff(a, b));
+        }
+    }
+    let (a, b) = (a.score(rules), b.score(rules));
+    println!();
+    for (name, a, b) in [
+        ("upper", a.upper, b.upper),
+        ("bonus", a.bonus, b.bonus),
+        ("lower", a.lower, b.lower),
+        ("total", a.total(), b.total()),
+    ] {
+        println!(
+            "{name:15} {} {}{}",
+            cell(Some(a)),
+            cell(Some(b)),
+            diff(Some(a), Some(b))
+        );
+    }
+}
+
+fn read_scorecard(path: &Path) -> Result<Vec<(Combination, DiceRolls)>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let (name, faces) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {}: expected `<box>: <dice>`", n + 1))?;
+            name.trim()
+                .parse()
+                .and_then(|combination| Ok((combination, parse_dice(faces)?)))
+                .map_err(|e| format!("line {}: {e}", n + 1))
+        })
+        .collect()
+}
+
+// The box the computer records the dice in: the best one once the rest of
+// the game can be solved, otherwise the one that scores most right now, or
+// the cheapest one to cross out. With `bonus_weighting`, what an upper box
+// adds to the expected bonus counts towards its score.
+fn computer_record(
+    valued_combinations: &[ValuedCombination],
+    dice: DiceRolls,
+    player_state: &PlayerState,
+    rules: &Rules,
+    endgame: &mut Endgame,
+    bonus_weighting: bool,
+) -> usize {
+    if let Some((open, upper)) = endgame.state(player_state) {
+        let (best, _) = endgame.best_record(dice, open, upper);
+        valued_combinations
+            .iter()
+            .position(|vc| vc.combination == best)
+            .unwrap()
+    } else if valued_combinations.iter().all(|vc| vc.value == 0) {
+        cheapest_sacrifice(valued_combinations, &rules.scoring)
+    } else if bonus_weighting {
+        let worth = |vc: &ValuedCombination| {
+            let mut worth = vc.value as f64;
+            if vc.combination.is_upper() {
+                worth += player_state.bonus_gain(rules, vc);
+            }
+            worth
+        };
+        (0..valued_combinations.len())
+            .map(|idx| (idx, worth(&valued_combinations[idx])))
+            .reduce(|best, next| if next.1 > best.1 { next } else { best })
+            .unwrap()
+            .0
+    } else {
+        (0..valued_combinations.len())
+            .max_by_key(|&idx| valued_combinations[idx].value)
+            .unwrap()
+    }
+}
+
+// How well the computer plays: hard plays the last few turns perfectly,
+// medium keeps to the greedy heuristic until the end, and easy on top of that
+// now and then keeps the dice for some other open box than the best one.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Difficulty {
+    Easy,
+    Medium,
+    #[default]
+    Hard,
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty: {s}")),
+        }
+    }
+}
+
+// Plays a whole game without asking: every roll chases the open box with the
+// best chance of completing it, weighted by what completing it is worth, and
+// every turn records whatever scores the most. On hard, the last few turns are
+// played perfectly instead, see `Endgame`. What it has worked out is kept for
+// the next game.
+struct GreedyPlayer {
+    odds: Vec<TargetOdds>,
+    endgame: Endgame,
+    bonus_weighting: bool,
+    difficulty: Difficulty,
+    rng: fastrand::Rng,
+}
+
+impl GreedyPlayer {
+    // on easy, one keep in this many chases a random open box
+    const EASY_SLIP: usize = 4;
+
+    fn new(
+        rules: &Rules,
+        bonus_weighting: bool,
+        difficulty: Difficulty,
+        seed: Option<u64>,
+    ) -> Self {
+        let mut endgame = Endgame::new(rules);
+        endgame.solvable &= difficulty == Difficulty::Hard;
+        Self {
+            odds: rules
+                .combinations()
+                .filter(|&combination| combination != Combination::Chance)
+                .map(TargetOdds::new)
+                .collect(),
+            endgame,
+            bonus_weighting,
+            difficulty,
+            rng: match seed {
+                Some(seed) => fastrand::Rng::with_seed(seed),
+                None => fastrand::Rng::new(),
+            },
+        }
+    }
+
+    // every turn is told to `out`, turn by turn
+    fn play(
+        &mut self,
+        player_state: &mut PlayerState,
+        source: &mut dyn DiceSource,
+        rules: &Rules,
+        out: &mut dyn std::io::Write,
+    ) -> std::io::Result<()> {
+        let GreedyPlayer {
+            odds,
+            endgame,
+            bonus_weighting,
+            difficulty,
+            rng,
+        } = self;
+        let show = |dice: &[DieRoll]| {
+            let faces: Vec<String> = dice.iter().map(DieRoll::to_string).collect();
+            faces.join(" ")
+        };
+        while !player_state.is_done(rules) {
+            writeln!(out, "Turn {}", player_state.filled.len() + 1)?;
+            let mut dice: DiceRolls = [DieRoll::One; 5];
+            source.roll(&mut dice, &[0, 1, 2, 3, 4])?;
+            let mut rolls = 1;
+            let endgame_state = endgame.state(player_state);
+            loop {
+                dice.sort();
+                let rerolls_left = rules.rerolls + 1 - rolls;
+                if rerolls_left == 0 {
+                    writeln!(out, "  rolled {}", show(&dice))?;
+                    break;
+                }
+                let mut goals: Vec<(Combination, Vec<usize>, f64)> = odds
+                    .iter_mut()
+                    .filter(|odds| !player_state.has_combination(odds.target))
+                    .map(|odds| {
+                        let combination = odds.target;
+                        let (keep, p) = odds.best_keep(dice, rerolls_left);
+                        let worth = match combination {
+                            Combination::Upper(die) => 3 * die as u16,
+                            _ => rules.scoring.max_value(combination),
+                        };
+                        (combination, keep, p * worth as f64)
+                    })
+                    .collect();
+                let slip = *difficulty == Difficulty::Easy
+                    && !goals.is_empty()
+                    && rng.usize(..Self::EASY_SLIP) == 0;
+                let goal = if slip {
+                    Some(goals.swap_remove(rng.usize(..goals.len())))
+                } else {
+                    goals.into_iter().max_by(|a, b| a.2.total_cmp(&b.2))
+                };
+                let (goal, keep) = match (endgame_state, goal) {
+                    (Some((open, upper)), _) => (
+                        "the best finish".to_string(),
+                        endgame.best_keep(dice, rerolls_left, open, upper).0,
+                    ),
+                    (None, Some((goal, keep, _))) => (goal.to_string(), keep),
+                    (None, None) => (
+                        Combination::Chance.to_string(),
+                        best_chance_keep(dice, rerolls_left).0,
+                    ),
+                };
+                let kept: Vec<DieRoll> = keep.iter().map(|&idx| dice[idx]).collect();
+                if kept.len() == 5 {
+                    writeln!(out, "  rolled {}, keeping everything", show(&dice))?;
+                    break;
+                }
+                writeln!(
+                    out,
+                    "  rolled {}, keeping [{}] for {goal}",
+                    show(&dice),
+                    show(&kept)
+                )?;
+                let rerolled: Vec<usize> = (0..5).filter(|idx| !keep.contains(idx)).collect();
+                source.roll(&mut dice, &rerolled)?;
+                rolls += 1;
+            }
+            let values = PotentialValues::new(DiceCounts::new(dice), &rules.scoring);
+            let mut valued_combinations: Vec<ValuedCombination> = player_state
+                .open_combinations(rules)
+                .map(|combination| ValuedCombination {
+                    combination,
+                    column: player_state.open_columns(combination)[0],
+                    value: values[combination],
+                    note: None,
+                    natural: false,
+                    dice,
+                })
+                .collect();
+            rules.filter_recordable(&mut valued_combinations, rolls);
+            let selection = computer_record(
+                &valued_combinations,
+                dice,
+                player_state,
+                rules,
+                endgame,
+                *bonus_weighting,
+            );
+            let vc = valued_combinations.swap_remove(selection);
+            writeln!(out, "  recorded {} ({})", vc.combination, vc.value)?;
+            player_state
+                .record_value(vc)
+                .expect("recorded combination should have been open");
+            writeln!(out, "  {}", player_state.score(rules))?;
+        }
+        Ok(())
+    }
+}
+
+// How a partly played game goes on: the box the computer records most often
+// on the next turn, how often it does so out of `SOLVE_GAMES` games, and the
+// average final total of those games. Once the endgame can be solved, the
+// total is the exact expectation instead.
+const SOLVE_GAMES: u64 = 100;
+
+fn solve_scorecard(
+    player_state: &PlayerState,
+    rules: &Rules,
+    bonus_weighting: bool,
+    seed: u64,
+    weights: Option<[u32; 6]>,
+) -> std::io::Result<Option<(Combination, u64, f64)>> {
+    if player_state.is_done(rules) {
+        return Ok(None);
+    }
+    let mut player = GreedyPlayer::new(rules, bonus_weighting, Difficulty::Hard, None);
+    if player.endgame.state(player_state).is_none() {
+        // every game would reach an endgame of its own, and solving each of
+        // them takes seconds
+        player.endgame.solvable = false;
+    }
+    let mut next = Vec::new();
+    let mut total = 0;
+    for game in 0..SOLVE_GAMES {
+        let mut finished = player_state.clone();
+        let mut source = random_source(Some(seed.wrapping_add(game)), weights);
+        player.play(&mut finished, &mut *source, rules, &mut std::io::sink())?;
+        next.push(finished.filled[player_state.filled.len()].combination);
+        total += finished.score(rules).total() as u64;
+    }
+    // ties go to the box listed first on the scorecard
+    let (combination, count) = rules
+        .combinations()
+        .map(|combination| {
+            let count = next.iter().filter(|&&c| c == combination).count();
+            (combination, count as u64)
+        })
+        .reduce(|best, next| if next.1 > best.1 { next } else { best })
+        .unwrap();
+    let expected = match player.endgame.state(player_state) {
+        Some((open, upper)) => {
+            player_state.score(rules).total() as f64 + player.endgame.expected(open, upper)
+        }
+        None => total as f64 / SOLVE_GAMES as f64,
+    };
+    Ok(Some((combination, count, expected)))
+}
+
+fn greedy_playthrough(
+    player_state: &mut PlayerState,
+    source: &mut dyn DiceSource,
+    rules: &Rules,
+    bonus_weighting: bool,
+    difficulty: Difficulty,
+    seed: Option<u64>,
+) -> std::io::Result<()> {
+    GreedyPlayer::new(rules, bonus_weighting, difficulty, seed).play(
+        player_state,
+        source,
+        rules,
+        &mut std::io::stdout(),
+    )
+}
+
+// The little of JSON that protocol commands need. Numbers are whole and not
+// negative, as they only ever pick dice.
+#[derive(Debug, PartialEq)]
+enum Json {
+    String(String),
+    Number(u64),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(s: &str) -> Result<Json, String> {
+        let mut chars = s.chars().peekable();
+        let value = Json::parse_value(&mut chars)?;
+        Json::skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected {c:?} after the value")),
+        }
+    }
+
+    fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn parse_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Json, String> {
+        Json::skip_whitespace(chars);
+        match chars.peek().copied() {
+            Some('"') => Json::parse_string(chars).map(Json::String),
+            Some('0'..='9') => {
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                let number = digits
+                    .parse()
+                    .map_err(|_| format!("number too large: {digits}"))?;
+                Ok(Json::Number(number))
+            }
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                Json::skip_whitespace(chars);
+                if chars.next_if_eq(&']').is_none() {
+                    loop {
+                        items.push(Json::parse_value(chars)?);
+                        Json::skip_whitespace(chars);
+                        match chars.next() {
+                            Some(',') => {}
+                            Some(']') => break,
+                            _ => return Err("expected , or ] in an array".to_string()),
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some('{') => {
+                chars.next();
+                let mut members = Vec::new();
+                Json::skip_whitespace(chars);
+                if chars.next_if_eq(&'}').is_none() {
+                    loop {
+                        Json::skip_whitespace(chars);
+                        if chars.peek() != Some(&'"') {
+                            return Err("expected a string as key".to_string());
+                        }
+                        let key = Json::parse_string(chars)?;
+                        Json::skip_whitespace(chars);
+                        if chars.next() != Some(':') {
+                            return Err(format!("expected : after {key:?}"));
+                        }
+                        members.push((key, Json::parse_value(chars)?));
+                        Json::skip_whitespace(chars);
+                        match chars.next() {
+                            Some(',') => {}
+                            Some('}') => break,
+                            _ => return Err("expected , or } in an object".to_string()),
+                        }
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            Some(c) => Err(format!("unexpected {c:?}")),
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    // starts at the opening quote
+    fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+        chars.next();
+        let mut string = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match chars.next() {
+                    Some(c @ ('"' | '\\' | '/')) => string.push(c),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape: \\u{hex}"))?;
+                        string.push(c);
+                    }
+                    c => return Err(format!("invalid escape: {c:?}")),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+enum ProtocolCommand {
+    Reroll(Vec<usize>),
+    Record(Combination),
+}
+
+impl std::str::FromStr for ProtocolCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = Json::parse(s)?;
+        match json.get("command") {
+            Some(Json::String(command)) if command == "reroll" => {
+                let Some(Json::Array(items)) = json.get("indices") else {
+                    return Err("reroll needs an array of indices".to_string());
+                };
+                let mut indices = Vec::new();
+                for item in items {
+                    match item {
+                        Json::Number(idx) if *idx < 5 && !indices.contains(&(*idx as usize)) => {
+                            indices.push(*idx as usize)
+                        }
+                        _ => return Err("indices must be distinct dice from 0 to 4".to_string()),
+                    }
+                }
+                if indices.is_empty() {
+                    return Err("reroll needs at least one die".to_string());
+                }
+                Ok(ProtocolCommand::Reroll(indices))
+            }
+            Some(Json::String(command)) if command == "record" => {
+                let Some(Json::String(combination)) = json.get("combination") else {
+                    return Err("record needs a combination".to_string());
+                };
+                let combination = combination.parse().map_err(|e: GameError| e.to_string())?;
+                Ok(ProtocolCommand::Record(combination))
+            }
+            Some(Json::String(command)) => Err(format!("unknown command: {command}")),
+            _ => Err("missing command".to_string()),
+        }
+    }
+}
+
+fn score_json(score: &Score) -> String {
+    format!(
+        r#"{{"upper":{},"bonus":{},"lower":{},"total":{}}}"#,
+        score.upper,
+        score.bonus,
+        score.lower,
+        score.total()
+    )
+}
+
+// A line-oriented protocol for other frontends, e.g. a GUI: before every
+// decision a state message is written, and every line read is one command.
+// The messages are described in the readme. A game ends early when the input
+// does.
+fn play_protocol(
+    source: &mut dyn DiceSource,
+    rules: &Rules,
+    input: &mut dyn std::io::BufRead,
+    out: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    let mut player_state = PlayerState::new(rules.columns);
+    let mut line = String::new();
+    while !player_state.is_done(rules) {
+        let mut dice = roll_turn(source, &player_state, rules)?;
+        let mut rolls = 1;
+        loop {
+            let mut valued_combinations = player_state.ranked_combinations(rules, dice);
+            rules.filter_recordable(&mut valued_combinations, rolls);
+            let faces: Vec<String> = dice.iter().map(|&die| (die as u8).to_string()).collect();
+            let open: Vec<String> = valued_combinations
+                .iter()
+                .map(|vc| json_string(vc.combination.key()))
+                .collect();
+            let values: Vec<String> = valued_combinations
+                .iter()
+                .map(|vc| format!("{}:{}", json_string(vc.combination.key()), vc.value))
+                .collect();
+            writeln!(
+                out,
+                r#"{{"type":"state","turn":{},"rolls_left":{},"dice":[{}],"open_combinations":[{}],"values":{{{}}},"score":{}}}"#,
+                player_state.filled.len() + 1,
+                rules.rerolls + 1 - rolls,
+                faces.join(","),
+                open.join(","),
+                values.join(","),
+                score_json(&player_state.score(rules))
+            )?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let error = match line.parse() {
+                Ok(ProtocolCommand::Reroll(_)) if rolls > rules.rerolls => {
+                    "no rerolls left".to_string()
+                }
+                Ok(ProtocolCommand::Reroll(indices)) => {
+                    let before = dice;
+                    source.roll(&mut dice, &indices)?;
+                    rolls += 1;
+                    let best = |dice: DiceRolls| {
+                        PotentialValues::new(DiceCounts::new(dice), &rules.scoring)
+                            .best(player_state.open_combinations(rules))
+                            .map_or(0, |(_, value)| value)
+                    };
+                    if rules.must_improve && best(dice) < best(before) {
+                        dice = before;
+                    }
+                    continue;
+                }
+                Ok(ProtocolCommand::Record(combination)) => {
+                    match valued_combinations
+                        .into_iter()
+                        .find(|vc| vc.combination == combination)
+                    {
+                        Some(mut vc) => {
+                            vc.column = player_state.open_columns(combination)[0];
+                            player_state
+                                .record_value(vc)
+                                .expect("recorded combination should have been open");
+                            break;
+                        }
+                        None => format!("cannot record {} now", combination.key()),
+                    }
+                }
+                Err(error) => error,
+            };
+            writeln!(
+                out,
+                r#"{{"type":"error","message":{}}}"#,
+                json_string(&error)
+            )?;
+        }
+    }
+    writeln!(
+        out,
+        r#"{{"type":"done","score":{}}}"#,
+        score_json(&player_state.score(rules))
+    )
+}
+
+#[derive(Clone, Copy, Default)]
+enum Palette {
+    #[default]
+    Default,
+    Colorblind,
+    Mono,
+}
+
+impl Palette {
+    // with `zeros_only`, only crossed out boxes stand out
+    fn recorded<D>(self, d: D, value: u16, zeros_only: bool) -> console::StyledObject<D> {
+        if zeros_only && value > 0 {
+            return console::Style::new().apply_to(d);
+        }
+        let style = console::Style::new().strikethrough();
+        let style = match self {
+            Palette::Default if value == 0 => style.red(),
+            Palette::Default => style.green(),
+            Palette::Colorblind if value == 0 => style.color256(208),
+            Palette::Colorblind => style.blue().bright(),
+            Palette::Mono => style,
+        };
+        style.apply_to(d)
+    }
+
+    fn suggested<D>(self, d: D) -> console::StyledObject<D> {
+        let style = console::Style::new().underlined().bold();
+        let style = match self {
+            Palette::Default => style.green(),
+            Palette::Colorblind => style.blue().bright(),
+            Palette::Mono => style,
+        };
+        style.apply_to(d)
+    }
+
+    // without colors, suggested dice need a mark besides their style
+    fn marks_suggested(self) -> bool {
+        matches!(self, Palette::Mono) || !console::colors_enabled()
+    }
+
+    fn trophy<D>(self, d: D) -> console::StyledObject<D> {
+        let style = console::Style::new().bold();
+        let style = match self {
+            Palette::Default | Palette::Colorblind => style.yellow(),
+            Palette::Mono => style,
+        };
+        style.apply_to(d)
+    }
+
+    // how much more or less B scored than A, with ▲/▼ unless colors tell
+    fn difference(self, diff: i32) -> String {
+        let text = format!("{diff:+6}");
+        let style = console::Style::new();
+        let (style, glyph) = match self {
+            Palette::Default if diff > 0 => (style.green(), ""),
+            Palette::Default => (style.red(), ""),
+            Palette::Colorblind if diff > 0 => (style.blue().bright(), " ▲"),
+            Palette::Colorblind => (style.color256(208), " ▼"),
+            Palette::Mono if diff > 0 => (style, " ▲"),
+            Palette::Mono => (style, " ▼"),
+        };
+        format!("{}{glyph}", style.apply_to(text))
+    }
+
+    fn progress_bar(self, progress: u16) -> String {
+        const WIDTH: usize = 12;
+        let (full, empty) = match self {
+            Palette::Mono => ('#', '-'),
+            _ if !console::colors_enabled() => ('#', '-'),
+            Palette::Default | Palette::Colorblind => ('█', '░'),
+        };
+        let filled = WIDTH * progress.min(BONUS_THRESHOLD) as usize / BONUS_THRESHOLD as usize;
+        let bar: String = std::iter::repeat_n(full, filled)
+            .chain(std::iter::repeat_n(empty, WIDTH - filled))
+            .collect();
+        format!("[{bar}] {progress}/{BONUS_THRESHOLD}")
+    }
+
+    fn marker(self, value: u16) -> &'static str {
+        match self {
+            Palette::Default => "",
+            Palette::Colorblind | Palette::Mono if value == 0 => " ✗",
+            Palette::Colorblind | Palette::Mono => " ✓",
+        }
+    }
+}
+
+impl std::str::FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Palette::Default),
+            "colorblind" => Ok(Palette::Colorblind),
+            "mono" => Ok(Palette::Mono),
+            _ => Err(format!("unknown palette: {s}")),
+        }
+    }
+}
+
+#[derive(Default)]
+enum DiceSourceKind {
+    #[default]
+    Random,
+    Manual,
+    File(PathBuf),
+}
+
+impl std::str::FromStr for DiceSourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(DiceSourceKind::Random),
+            "manual" => Ok(DiceSourceKind::Manual),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(DiceSourceKind::File(path.into())),
+                _ => Err(format!("unknown dice source: {s}")),
+            },
+        }
+    }
+}
+
+// Seeds are accepted both as plain numbers and as the game IDs that are
+// shown to the player, which are the seed in hex in groups of four digits.
+fn parse_seed(seed: &str) -> Result<u64, String> {
+    let parsed = if seed.contains('-') {
+        u64::from_str_radix(&seed.replace('-', ""), 16)
+    } else {
+        seed.parse()
+    };
+    parsed.map_err(|_| format!("invalid seed: {seed}"))
+}
+
+fn game_id(seed: u64) -> String {
+    let hex = format!("{seed:016x}");
+    let groups: Vec<&str> = (0..hex.len()).step_by(4).map(|i| &hex[i..i + 4]).collect();
+    groups.join("-")
+}
+
+fn clock_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+#[derive(Default)]
+struct Options {
+    notes: bool,
+    target: bool,
+    palette: Palette,
+    incremental: bool,
+    dice_source: DiceSourceKind,
+    debug_dice: bool,
+    rules: Rules,
+    timings: bool,
+    rule_variants: bool,
+    lock_mode: bool,
+    auto_sacrifice: bool,
+    confirm_reroll: bool,
+    reroll_preview: bool,
+    percentages: bool,
+    compact: bool,
+    scoresheet: bool,
+    no_clear: bool,
+    shuffle_menu: bool,
+    show_gain: bool,
+    coach: bool,
+    names: Vec<(Combination, String)>,
+    show_ai: bool,
+    bonus_weighting: bool,
+    ai_difficulty: Difficulty,
+    bonus_hints: bool,
+    study: Option<f64>,
+    strike_zeros: bool,
+    playthrough: Option<u64>,
+    json_protocol: bool,
+    seed: Option<u64>,
+    dice_weights: Option<[u32; 6]>,
+    solve: Option<PathBuf>,
+    compare: Option<(PathBuf, PathBuf)>,
+    stats: Option<PathBuf>,
+    animate: Option<Duration>,
+    sacrifice_report: bool,
+    shortcuts: bool,
+    big_dice: bool,
+    reroll_stats: bool,
+    broadcast: Option<String>,
+    edit_rules: Option<PathBuf>,
+    evaluate: Option<PathBuf>,
+    export_image: Option<PathBuf>,
+    metrics: Option<PathBuf>,
+}
+
+// A config file holds options like on the command line, one per line and
+// without the leading dashes, e.g. `seed 42` or `tiny-straight`. Empty lines
+// and lines starting with `#` are skipped.
+fn read_config(path: &Path) -> Result<Vec<String>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let mut args = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let name = name.trim_start_matches('-');
+        args.push(format!("--{name}"));
+        // a display name is the rest of the line, so that it may have spaces
+        match rest.trim().split_once(char::is_whitespace) {
+            Some((key, label)) if name == "name" => {
+                args.push(key.to_string());
+                args.push(label.trim().to_string());
+            }
+            _ => args.extend(rest.split_whitespace().map(str::to_string)),
+        }
+    }
+    Ok(args)
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        // the config file goes first, so that the command line overrides it
+        if let Some(idx) = args.iter().position(|arg| arg == "--config") {
+            let path = args
+                .get(idx + 1)
+                .ok_or("missing value for --config")?
+                .clone();
+            args.drain(idx..idx + 2);
+            let mut config = read_config(Path::new(&path))?;
+            config.append(&mut args);
+            args = config;
+        }
+        Self::parse(args.into_iter())
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--notes" => options.notes = true,
+                "--target" => options.target = true,
+                "--incremental" => options.incremental = true,
+                "--lock-mode" => options.lock_mode = true,
+                "--confirm-reroll" => options.confirm_reroll = true,
+                "--reroll-preview" => options.reroll_preview = true,
+                "--percentages" => options.percentages = true,
+                "--compact" => options.compact = true,
+                "--scoresheet" => options.scoresheet = true,
+                "--no-clear" => options.no_clear = true,
+                "--shuffle-menu" => options.shuffle_menu = true,
+                "--show-gain" => options.show_gain = true,
+                "--coach" => options.coach = true,
+                "--name" => {
+                    let combination: Combination =
+                        value()?.parse().map_err(|e: GameError| e.to_string())?;
+                    let name = value()?;
+                    if name.is_empty() || name.width() > MAX_NAME_WIDTH {
+                        return Err(format!(
+                            "names must be 1 to {MAX_NAME_WIDTH} columns wide: {name}"
+                        ));
+                    }
+                    options.names.retain(|(c, _)| *c != combination);
+                    options.names.push((combination, name));
+                }
+                "--show-ai" => options.show_ai = true,
+                "--bonus-weighting" => options.bonus_weighting = true,
+                "--ai-difficulty" => options.ai_difficulty = value()?.parse()?,
+                "--bonus-hints" => options.bonus_hints = true,
+                "--study" => {
+                    let points = value()?;
+                    options.study = match points.parse() {
+                        Ok(points) if points >= 0.0 => Some(points),
+                        _ => return Err(format!("invalid number of points: {points}")),
+                    };
+                }
+                "--strike-zeros" => options.strike_zeros = true,
+                "--playthrough" => options.playthrough = Some(parse_seed(&value()?)?),
+                "--protocol" => match value()?.as_str() {
+                    "json" => options.json_protocol = true,
+                    protocol => return Err(format!("unknown protocol: {protocol}")),
+                },
+                "--seed" => options.seed = Some(parse_seed(&value()?)?),
+                "--dice-weights" => options.dice_weights = Some(parse_weights(&value()?)?),
+                "--solve" => options.solve = Some(value()?.into()),
+                "--animate" => {
+                    let ms = value()?;
+                    let ms = ms.parse().map_err(|_| format!("invalid duration: {ms}"))?;
+                    options.animate = Some(Duration::from_millis(ms));
+                }
+                "--stats" => options.stats = Some(value()?.into()),
+                "--sacrifice-report" => options.sacrifice_report = true,
+                "--compare" => options.compare = Some((value()?.into(), value()?.into())),
+                "--shortcuts" => options.shortcuts = true,
+                "--big-dice" => options.big_dice = true,
+                "--broadcast" => options.broadcast = Some(value()?),
+                "--edit-rules" => options.edit_rules = Some(value()?.into()),
+                "--evaluate" => options.evaluate = Some(value()?.into()),
+                "--manual-dice" => options.dice_source = DiceSourceKind::Manual,
+                "--dice-source" => options.dice_source = value()?.parse()?,
+                "--debug-dice" => options.debug_dice = true,
+                "--timings" => options.timings = true,
+                "--rule-variants" => options.rule_variants = true,
+                "--reroll-stats" => options.reroll_stats = true,
+                "--auto-sacrifice" => options.auto_sacrifice = true,
+                "--forced-upper" => options.rules.forced_upper = true,
+                "--tiny-straight" => options.rules.tiny_straight = true,
+                "--two-pairs" => options.rules.two_pairs = true,
+                "--upper-only" => options.rules.upper_only = true,
+                "--must-improve" => options.rules.must_improve = true,
+                "--no-bonus" => options.rules.bonus = BonusMode::None,
+                "--no-rerolls" => options.rules.rerolls = 0,
+                "--columns" => {
+                    let columns = value()?;
+                    options.rules.columns = match columns.parse() {
+                        Ok(columns) if columns > 0 => columns,
+                        _ => return Err(format!("invalid number of columns: {columns}")),
+                    };
+                }
+                "--full-house-sum" => options.rules.scoring.full_house_as_sum = true,
+                "--chance" => options.rules.scoring.chance = value()?.parse()?,
+                "--palette" => options.palette = value()?.parse()?,
+                "--export-image" if cfg!(feature = "svg") => {
+                    options.export_image = Some(value()?.into())
+                }
+                "--export-image" => {
+                    return Err("--export-image needs a build with `--features svg`".to_string())
+                }
+                "--metrics" if cfg!(feature = "metrics") => options.metrics = Some(value()?.into()),
+                "--metrics" => {
+                    return Err("--metrics needs a build with `--features metrics`".to_string())
+                }
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+        if options.incremental {
+            let ignored = [
+                (options.lock_mode, "--lock-mode"),
+                (options.confirm_reroll, "--confirm-reroll"),
+                (options.reroll_preview, "--reroll-preview"),
+                (options.study.is_some(), "--study"),
+            ];
+            if let Some((_, flag)) = ignored.iter().find(|(on, _)| *on) {
+                return Err(format!("--incremental cannot be combined with {flag}"));
+            }
+        }
+        Ok(options)
+    }
+}
+
+const TROPHY: &str = r"
+   ___________
+  '._==_==_=_.'
+  .-\:      /-.
+ | (|:.     |) |
+  '-|:.     |-'
+    \::.    /
+     '::. .'
+       ) (
+     _.' '._
+    '-------'";
+
+// Box names can be chosen with `--name`, so they may hold markup.
+#[cfg(feature = "svg")]
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(feature = "svg")]
+fn export_svg(
+    player_state: &PlayerState,
+    rules: &Rules,
+    path: &std::path::Path,
+) -> std::io::Result<()> {
+    use std::fmt::Write;
+
+    const ROW: usize = 24;
+    let row = |combination: Combination| {
+        let values: Vec<String> = (0..player_state.columns)
+            .map(|column| match player_state.find(column, combination) {
+                Some(vc) => vc.value.to_string(),
+                None if player_state.columns > 1 => "–".to_string(),
+                None => String::new(),
+            })
+            .collect();
+        (xml_escape(&combination.to_string()), values.join(" "))
+    };
+    let (upper, lower): (Vec<Combination>, Vec<Combination>) =
+        rules.combinations().partition(Combination::is_upper);
+    let mut rows = [
+        upper.into_iter().map(row).collect::<Vec<_>>(),
+        lower.into_iter().map(row).collect(),
+    ];
+    let score = player_state.score(rules);
+    rows[0].push(("bonus".to_string(), score.bonus.to_string()));
+    let height = ROW * (rows.iter().map(Vec::len).max().unwrap() + 3);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="440" height="{height}" font-family="sans-serif" font-size="16">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    for (column, rows) in rows.iter().enumerate() {
+        let x = 20 + column * 220;
+        for (row, (name, value)) in rows.iter().enumerate() {
+            let y = ROW * (row + 1);
+            let _ = writeln!(svg, r#"<text x="{x}" y="{y}">{name}</text>"#);
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{y}" text-anchor="end">{value}</text>"#,
+                x + 190
+            );
+        }
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="20" y="{}" font-weight="bold">Total: {}</text>"#,
+        height - ROW,
+        score.total()
+    );
+    svg.push_str("</svg>\n");
+    std::fs::write(path, svg)
+}
+
+// Every value that a box can score under the rules, each with dice that
+// score it.
+fn possible_values(combination: Combination, scoring: &ScoringTable) -> Vec<(u16, DiceRolls)> {
+    let mut values: Vec<(u16, DiceRolls)> = Vec::new();
+    for (faces, _) in reroll_outcomes(5) {
+        let dice: DiceRolls = faces.try_into().unwrap();
+        let value = PotentialValues::new(DiceCounts::new(dice), scoring)[combination];
+        if values.iter().all(|&(v, _)| v != value) {
+            values.push((value, dice));
+        }
+    }
+    values
+}
+
+// Only keeps the scorecard for a game played with real dice. The points of
+// every box are checked against what its dice could have scored.
+// asks which open column `combination` goes in, if there is a choice
+fn choose_column(player_state: &PlayerState, combination: Combination, verb: &str) -> usize {
+    let open_columns = player_state.open_columns(combination);
+    if open_columns.len() > 1 {
+        let labels: Vec<String> = open_columns
+            .iter()
+            .map(|column| format!("column {}", column + 1))
+            .collect();
+        let selection = dialoguer::Select::new()
+            .with_prompt(format!(
+                "Which column do you want to {verb} {combination} in?"
+            ))
+            .items(&labels)
+            .default(0)
+            .interact()
+            .unwrap();
+        open_columns[selection]
+    } else {
+        open_columns.first().copied().unwrap_or(0)
+    }
+}
+
+fn keep_scoresheet(
+    term: &console::Term,
+    rules: &Rules,
+    palette: Palette,
+    strike_zeros: bool,
+) -> std::io::Result<PlayerState> {
+    let mut player_state = PlayerState::new(rules.columns);
+    while !player_state.is_done(rules) {
+        term.clear_screen()?;
+        player_state.display(rules, palette, true, strike_zeros)?;
+        println!();
+        let open: Vec<Combination> = player_state.open_combinations(rules).collect();
+        let selection = dialoguer::Select::new()
+            .with_prompt("Which box do you want to fill?")
+            .items(&open)
+            .default(0)
+            .interact()
+            .unwrap();
+        let combination = open[selection];
+        let column = choose_column(&player_state, combination, "fill");
+        let possible = possible_values(combination, &rules.scoring);
+        let value: u16 = dialoguer::Input::new()
+            .with_prompt(format!("Points for {combination}"))
+            .validate_with(|value: &u16| {
+                if possible.iter().any(|(v, _)| v == value) {
+                    Ok(())
+                } else {
+                    Err(format!("{combination} cannot score {value}"))
+                }
+            })
+            .interact_text()
+            .unwrap();
+        // the real dice are not known, so any that score the same stand in
+        let (_, dice) = possible.into_iter().find(|&(v, _)| v == value).unwrap();
+        player_state
+            .record_value(ValuedCombination {
+                combination,
+                column,
+                value,
+                note: None,
+                natural: false,
+                dice,
+            })
+            .expect("only open boxes are offered");
+    }
+    Ok(player_state)
+}
+
+fn display_trophy(player_state: &PlayerState, rules: &Rules, palette: Palette) {
+    println!("{}", palette.trophy(TROPHY));
+    println!();
+    println!(
+        "  Final score: {}",
+        palette.trophy(player_state.score(rules).total())
+    );
+}
+
+// Scores the recorded dice again as if the game had been played under other
+// rules, leaving out those that only change how a game is played.
+fn display_rule_variants(player_state: &PlayerState, rules: &Rules) {
+    let variants = [
+        ("standard", ScoringTable::default(), BonusMode::Standard),
+        ("no bonus", rules.scoring.clone(), BonusMode::None),
+        (
+            "full house as sum",
+            ScoringTable {
+                full_house_as_sum: true,
+                ..rules.scoring.clone()
+            },
+            rules.bonus,
+        ),
+        (
+            "chance fixed at 25",
+            ScoringTable {
+                chance: ChanceScoring::Fixed(25),
+                ..rules.scoring.clone()
+            },
+            rules.bonus,
+        ),
+    ];
+    println!("  Under other rules:");
+    for (name, scoring, bonus) in variants {
+        let filled = player_state
+            .filled
+            .iter()
+            .map(|vc| ValuedCombination {
+                value: PotentialValues::new(DiceCounts::new(vc.dice), &scoring)[vc.combination],
+                ..vc.clone()
+            })
+            .collect();
+        let rescored = PlayerState {
+            columns: player_state.columns,
+            filled,
+        };
+        let variant = Rules {
+            bonus,
+            scoring,
+            columns: rules.columns,
+            ..Rules::default()
+        };
+        println!("    {name:18} {:3}", rescored.score(&variant).total());
+    }
+}
+
+fn display_timings(timings: &[Duration]) {
+    const BUCKETS: [(u64, &str); 5] = [
+        (2, " < 2s"),
+        (5, " < 5s"),
+        (10, "< 10s"),
+        (30, "< 30s"),
+        (u64::MAX, ">=30s"),
+    ];
+    if timings.is_empty() {
+        return;
+    }
+    let total: Duration = timings.iter().sum();
+    let average = total / timings.len() as u32;
+    let slowest = timings.iter().max().unwrap();
+    println!();
+    println!(
+        "Time per record: {:.1}s on average, {:.1}s at most",
+        average.as_secs_f64(),
+        slowest.as_secs_f64()
+    );
+    let mut lower = 0;
+    for (upper, label) in BUCKETS {
+        let count = timings
+            .iter()
+            .filter(|t| (lower..upper).contains(&t.as_secs()))
+            .count();
+        println!("  {label} {:2} {}", count, "█".repeat(count));
+        lower = upper;
+    }
+}
+
+// Every turn contributes the rerolls it used and how much more the recorded
+// box is worth than it would have been with the first roll of that turn.
+fn display_reroll_efficiency(turns: &[(u8, i32)]) {
+    let rerolls: u32 = turns.iter().map(|&(rerolls, _)| rerolls as u32).sum();
+    let gained: i32 = turns.iter().map(|&(_, gain)| gain).sum();
+    println!();
+    if rerolls == 0 {
+        println!("No rerolls used");
+        return;
+    }
+    println!(
+        "Rerolls used: {rerolls}, {:+.2} points gained per reroll",
+        gained as f64 / rerolls as f64
+    );
+}
+
+// Lifetime statistics, kept in a plain text file with a `<name> <count>` line
+// each for the number of games, the points and the bonuses scored in them,
+// and for how often every box was crossed out.
+struct Stats {
+    games: u32,
+    points: u32,
+    bonuses: u32,
+    zeros: Vec<(Combination, u32)>,
+}
+
+impl Stats {
+    fn load(path: &Path) -> std::io::Result<Self> {
+        let mut stats = Stats {
+            games: 0,
+            points: 0,
+            bonuses: 0,
+            zeros: Combination::all()
+                .map(|combination| (combination, 0))
+                .collect(),
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stats),
+            Err(e) => return Err(e),
+        };
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: invalid line: {line}", path.display()),
+                )
+            };
+            let (name, count) = line.split_once(' ').ok_or_else(invalid)?;
+            let count = count.trim().parse().map_err(|_| invalid())?;
+            match name {
+                "games" => stats.games = count,
+                "points" => stats.points = count,
+                "bonuses" => stats.bonuses = count,
+                _ => {
+                    let combination: Combination = name.parse().map_err(|_| invalid())?;
+                    for (c, zeros) in &mut stats.zeros {
+                        if *c == combination {
+                            *zeros = count;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut content = format!(
+            "games {}\npoints {}\nbonuses {}\n",
+            self.games, self.points, self.bonuses
+        );
+        for (combination, count) in &self.zeros {
+            content += &format!("{} {count}\n", combination.key());
+        }
+        std::fs::write(path, content)
+    }
+
+    fn add_game(&mut self, player_state: &PlayerState, rules: &Rules) {
+        let score = player_state.score(rules);
+        self.games += 1;
+        self.points += score.total() as u32;
+        if score.bonus > 0 {
+            self.bonuses += 1;
+        }
+        for vc in player_state.filled.iter().filter(|vc| vc.value == 0) {
+            for (combination, count) in &mut self.zeros {
+                if *combination == vc.combination {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    fn display_sacrifices(&self) {
+        println!("Crossed out over {} games:", self.games);
+        let mut zeros: Vec<&(Combination, u32)> =
+            self.zeros.iter().filter(|(_, count)| *count > 0).collect();
+        zeros.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        for (combination, count) in zeros {
+            println!("  {combination:15} {count:4}");
+        }
+    }
+
+    // in the Prometheus text format
+    #[cfg(feature = "metrics")]
+    fn export_metrics(&self, path: &Path) -> std::io::Result<()> {
+        let games = self.games.max(1) as f64;
+        let mut content = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            content += &format!("# HELP kniffel_{name} {help}\n# TYPE kniffel_{name} {kind}\n");
+            for (labels, value) in samples {
+                content += &format!("kniffel_{name}{labels} {value}\n");
+            }
+        };
+        metric(
+            "games_total",
+            "counter",
+            "Finished games.",
+            &[(String::new(), self.games.to_string())],
+        );
+        metric(
+            "score_average",
+            "gauge",
+            "Average final score.",
+            &[(String::new(), (self.points as f64 / games).to_string())],
+        );
+        metric(
+            "bonus_rate",
+            "gauge",
+            "Share of games with the upper bonus.",
+            &[(String::new(), (self.bonuses as f64 / games).to_string())],
+        );
+        let zeros: Vec<(String, String)> = self
+            .zeros
+            .iter()
+            .map(|(combination, count)| {
+                (
+                    format!("{{box=\"{}\"}}", combination.key()),
+                    count.to_string(),
+                )
+            })
+            .collect();
+        metric(
+            "zeros_total",
+            "counter",
+            "Boxes crossed out with zero points.",
+            &zeros,
+        );
+        std::fs::write(path, content)
+    }
+}
+
+// Spectators get the latest snapshot when they connect and every later one as
+// it is sent. Clients that went away are dropped on the next write.
+struct Broadcast {
+    state: Arc<Mutex<(String, Vec<TcpStream>)>>,
+}
+
+impl Broadcast {
+    fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let state: Arc<Mutex<(String, Vec<TcpStream>)>> = Arc::default();
+        let shared = Arc::clone(&state);
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+                let (snapshot, clients) = &mut *shared.lock().unwrap();
+                if stream.write_all(snapshot.as_bytes()).is_ok() {
+                    clients.push(stream);
+                }
+            }
+        });
+        Ok(Self { state })
+    }
+
+    fn send(&self, snapshot: String) {
+        let (last, clients) = &mut *self.state.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(snapshot.as_bytes()).is_ok());
+        *last = snapshot;
+    }
+}
+
+// Five of a kind can go into its upper box instead, which scores less but
+// brings the bonus closer. Compares both for the first column where both are
+// open.
+fn quintuple_tradeoff(
+    player_state: &PlayerState,
+    rules: &Rules,
+    dice: DiceRolls,
+) -> Option<String> {
+    let die = dice[0];
+    if rules.bonus == BonusMode::None
+        || !rules.is_enabled(Combination::Quintuple)
+        || dice.iter().any(|&d| d != die)
+    {
+        return None;
+    }
+    let upper = Combination::Upper(die);
+    let column = (0..player_state.columns).find(|&column| {
+        player_state.find(column, Combination::Quintuple).is_none()
+            && player_state.find(column, upper).is_none()
+    })?;
+    let value = 5 * die as u16;
+    let mut with_upper = player_state.clone();
+    with_upper.filled.push(ValuedCombination {
+        combination: upper,
+        column,
+        value,
+        note: None,
+        natural: false,
+        dice,
+    });
+    let (before, after) = (
+        player_state.bonus_odds(rules, column),
+        with_upper.bonus_odds(rules, column),
+    );
+    Some(format!(
+        "{} scores 50, {upper} scores {value} and brings the upper section to {}/{BONUS_THRESHOLD}\n\
+         (bonus chance {:.0}% → {:.0}%, worth about {:.1} points)",
+        Combination::Quintuple,
+        with_upper.upper_progress(column),
+        before * 100.0,
+        after * 100.0,
+        (after - before) * 35.0
+    ))
+}
+
+// How far recording `value` in the upper box of `die` brings the first column
+// where it is open towards the bonus, e.g. "3 × ⚄ → +15 toward the bonus
+// (need 20)".
+fn bonus_hint(
+    player_state: &PlayerState,
+    rules: &Rules,
+    die: DieRoll,
+    value: u16,
+) -> Option<String> {
+    if rules.bonus == BonusMode::None {
+        return None;
+    }
+    let column = *player_state.open_columns(Combination::Upper(die)).first()?;
+    let need = BONUS_THRESHOLD.checked_sub(player_state.upper_progress(column))?;
+    if need == 0 {
+        return None;
+    }
+    let count = value / die as u16;
+    if value >= need {
+        Some(format!("{count} × {die} → +{value}, reaches the bonus"))
+    } else {
+        Some(format!(
+            "{count} × {die} → +{value} toward the bonus (need {need})"
+        ))
+    }
+}
+
+// The box that is cheapest to cross out, judged by how much it could score
+// at most.
+fn cheapest_sacrifice(valued_combinations: &[ValuedCombination], scoring: &ScoringTable) -> usize {
+    (0..valued_combinations.len())
+        .min_by_key(|&idx| scoring.max_value(valued_combinations[idx].combination))
+        .expect("there is always an open combination to record")
+}
+
+enum TurnAction {
+    Back,
+    Restart,
+    Pause,
+    Share,
+    Odds,
+    Takeover,
+    Quit,
+}
+
+fn turn_menu(restarted: bool) -> TurnAction {
+    let mut actions = vec![
+        (TurnAction::Back, "back to the dice"),
+        (TurnAction::Pause, "pause and hide the board"),
+        (TurnAction::Share, "show the dice as text to share"),
+        (TurnAction::Odds, "show the odds of each combination"),
+    ];
+    if !restarted {
+        actions.push((
+            TurnAction::Restart,
+            "restart the turn with a fresh roll (once per turn)",
+        ));
+    }
+    actions.push((TurnAction::Takeover, "let the computer finish the game"));
+    actions.push((TurnAction::Quit, "quit and forfeit the game"));
+    let labels: Vec<&str> = actions.iter().map(|(_, label)| *label).collect();
+    let selection = dialoguer::Select::new()
+        .with_prompt("What do you want to do?")
+        .items(&labels)
+        .default(0)
+        .interact()
+        .unwrap();
+    actions.swap_remove(selection).0
+}
+
+// e.g. "⚄⚄⚄⚁⚁ → full house 25"
+fn share_text(dice: &DiceRolls, best: Option<(Combination, u16)>) -> String {
+    let dice: String = dice.iter().map(|die| die.to_string()).collect();
+    match best {
+        Some((combination, value)) => format!("{dice} → {combination} {value}"),
+        None => dice,
+    }
+}
+
+// The chance of completing each box from a fresh roll within one roll, two
+// rolls and so on, playing for that box alone. Upper boxes count with three of
+// their face, as in `completes`.
+fn odds_table(rules: &Rules) -> Vec<(Combination, Vec<f64>)> {
+    rules
+        .combinations()
+        .filter(|&combination| combination != Combination::Chance)
+        .map(|combination| {
+            let mut odds = TargetOdds::new(combination);
+            let chances = (1..=rules.rerolls + 1)
+                .map(|rolls| odds.keep_probability([DieRoll::One; 5], &[], rolls))
+                .collect();
+            (combination, chances)
+        })
+        .collect()
+}
+
+// Shows the table a screenful at a time.
+fn display_odds_table(
+    term: &console::Term,
+    table: &[(Combination, Vec<f64>)],
+) -> std::io::Result<()> {
+    let mut header = format!("{:15}", "");
+    for rolls in 1..=table.first().map_or(0, |(_, chances)| chances.len()) {
+        header += &format!(
+            " {:>8}",
+            format!("{rolls} roll{}", if rolls == 1 { "" } else { "s" })
+        );
+    }
+    // room for the header, the note and the prompt
+    let page = (term.size().0 as usize).saturating_sub(5).max(1);
+    let pages = table.chunks(page).count();
+    for (number, rows) in table.chunks(page).enumerate() {
+        term.clear_screen()?;
+        println!("{header}");
+        for (combination, chances) in rows {
+            let mut row = format!("{combination:15}");
+            for chance in chances {
+                row += &format!(" {:>7.1}%", chance * 100.0);
+            }
+            println!("{row}");
+        }
+        println!("(upper boxes count with three of their face)");
+        if number + 1 < pages {
+            println!("Page {}/{pages} — press any key for more", number + 1);
+        } else {
+            println!("Press any key to continue");
+        }
+        term.read_key()?;
+    }
+    Ok(())
+}
+
+enum RerollChoice {
+    Reroll,
+    Record,
+    Change,
+}
+
+// The best, the worst and the average best score that the dice can be worth
+// after rolling the selected ones again.
+fn reroll_spread(
+    dice: &DiceRolls,
+    selection: &[usize],
+    best_value: impl Fn(DiceRolls) -> u16,
+) -> (u16, u16, f64) {
+    let (mut best, mut worst, mut average) = (0, u16::MAX, 0.0);
+    for (outcome, probability) in reroll_outcomes(selection.len()) {
+        let mut rerolled = *dice;
+        for (&idx, &die) in selection.iter().zip(&outcome) {
+            rerolled[idx] = die;
+        }
+        let value = best_value(rerolled);
+        best = best.max(value);
+        worst = worst.min(value);
+        average += probability * value as f64;
+    }
+    (best, worst, average)
+}
+
+fn confirm_reroll(dice: &DiceRolls, selection: &[usize]) -> RerollChoice {
+    let mut choices = Vec::new();
+    if !selection.is_empty() {
+        let chosen: Vec<String> = selection.iter().map(|&idx| dice[idx].to_string()).collect();
+        choices.push((
+            RerollChoice::Reroll,
+            format!("roll {} again", chosen.join(" ")),
+        ));
+    }
+    choices.push((RerollChoice::Record, "stop rolling and record".to_string()));
+    choices.push((RerollChoice::Change, "change the selection".to_string()));
+    let labels: Vec<&str> = choices.iter().map(|(_, label)| label.as_str()).collect();
+    let selection = dialoguer::Select::new()
+        .with_prompt("Are you sure?")
+        .items(&labels)
+        .default(0)
+        .interact()
+        .unwrap();
+    choices.swap_remove(selection).0
+}
+
+// `None` when Esc asks for the turn menu instead
+fn record_by_shortcut(
+    term: &console::Term,
+    valued_combinations: &[ValuedCombination],
+) -> std::io::Result<Option<usize>> {
+    println!("What combination do you want to record?");
+    for vc in valued_combinations {
+        println!("  [{}] {vc}", vc.combination.shortcut());
+    }
+    println!("  [Esc] more…");
+    loop {
+        match term.read_key()? {
+            console::Key::Char(c) => {
+                let position = valued_combinations
+                    .iter()
+                    .position(|vc| vc.combination.shortcut() == c.to_ascii_lowercase());
+                if position.is_some() {
+                    return Ok(position);
+                }
+            }
+            console::Key::Escape => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+// Each die can be rolled again at most once per reroll, so a die is still
+// rolled no more often than usual.
+fn reroll_incrementally(
+    dice: &mut DiceRolls,
+    source: &mut dyn DiceSource,
+) -> std::io::Result<Option<bool>> {
+    let mut rerolled = Vec::new();
+    loop {
+        let open: Vec<usize> = (0..dice.len())
+            .filter(|idx| !rerolled.contains(idx))
+            .collect();
+        if open.is_empty() {
+            break;
+        }
+        let mut items: Vec<String> = open.iter().map(|&idx| dice[idx].to_string()).collect();
+        items.push("done".to_string());
+        // the turn menu is only offered before the first die of the roll
+        if rerolled.is_empty() {
+            items.push("more…".to_string());
+        }
+        let selection = dialoguer::Select::new()
+            .with_prompt("Select a die to roll again")
+            .items(&items)
+            .default(open.len())
+            .interact()
+            .unwrap();
+        let Some(&idx) = open.get(selection) else {
+            if selection > open.len() {
+                return Ok(None);
+            }
+            break;
+        };
+        let old = dice[idx];
+        source.roll(dice, &[idx])?;
+        println!("{old} → {}", dice[idx]);
+        rerolled.push(idx);
+    }
+    Ok(Some(!rerolled.is_empty()))
+}
+
+// Waits for up to `timeout` and tells whether a key was pressed meanwhile,
+// without consuming it.
+#[cfg(unix)]
+fn key_pressed(timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+    unsafe { libc::poll(&mut fd, 1, timeout) > 0 }
+}
+
+#[cfg(not(unix))]
+fn key_pressed(timeout: Duration) -> bool {
+    std::thread::sleep(timeout);
+    false
+}
+
+// While it lives, keys reach stdin one by one and are not echoed, so that
+// `key_pressed` notices any key rather than only Enter. Whatever was typed
+// meanwhile is thrown away, so that it doesn't end up in the next prompt.
+#[cfg(unix)]
+struct KeyInput(Option<libc::termios>);
+
+// the settings `KeyInput` changes, kept for the signal handler of
+// `TerminalGuard`, as a Ctrl-C skips the `Drop`
+#[cfg(unix)]
+static SAVED_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+#[cfg(unix)]
+impl KeyInput {
+    fn new() -> Self {
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Self(None);
+            }
+            SAVED_TERMIOS.get_or_init(|| saved);
+            let mut keys = saved;
+            keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+            keys.c_cc[libc::VMIN] = 1;
+            keys.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys);
+            Self(Some(saved))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyInput {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.0 {
+            unsafe {
+                libc::tcflush(libc::STDIN_FILENO, libc::TCIFLUSH);
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+const ANIMATION_FRAME: Duration = Duration::from_millis(60);
+
+// Lets the rerolled dice tumble through random faces for `duration`. Any key
+// ends it early.
+fn animate_roll(
+    term: &console::Term,
+    dice: &DiceRolls,
+    rolled: &[usize],
+    duration: Duration,
+) -> std::io::Result<()> {
+    #[cfg(unix)]
+    let _keys = KeyInput::new();
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        let mut line = "You rolled:".to_string();
+        for (idx, die) in dice.iter().enumerate() {
+            let die = if rolled.contains(&idx) {
+                DIE_ROLLS[fastrand::usize(..DIE_ROLLS.len())]
+            } else {
+                *die
+            };
+            line += &format!(" {die}");
+        }
+        term.write_str(&line)?;
+        let left = duration.saturating_sub(started.elapsed());
+        let pressed = key_pressed(ANIMATION_FRAME.min(left));
+        term.clear_line()?;
+        if pressed {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn pips(die: DieRoll) -> [&'static str; 3] {
+    match die {
+        DieRoll::One => ["     ", "  o  ", "     "],
+        DieRoll::Two => ["o    ", "     ", "    o"],
+        DieRoll::Three => ["o    ", "  o  ", "    o"],
+        DieRoll::Four => ["o   o", "     ", "o   o"],
+        DieRoll::Five => ["o   o", "  o  ", "o   o"],
+        DieRoll::Six => ["o   o", "o   o", "o   o"],
+    }
+}
+
+// five dice of seven columns each, one column apart
+const BIG_DICE_WIDTH: u16 = 39;
+
+// Draws the dice side by side as pip grids, with the marked ones in the
+// style for suggested dice, and with double borders if that style may not
+// show.
+fn display_big_dice(dice: &DiceRolls, marked: impl Fn(usize) -> bool, palette: Palette) {
+    for row in 0..5 {
+        let mut line = Vec::new();
+        for (idx, &die) in dice.iter().enumerate() {
+            let part = match row {
+                0 | 4 if marked(idx) && palette.marks_suggested() => "+=====+".to_string(),
+                0 | 4 => "+-----+".to_string(),
+                _ => format!("|{}|", pips(die)[row - 1]),
+            };
+            if marked(idx) {
+                line.push(palette.suggested(part).to_string());
             } else {
-                println!("{combination:15}     ");
+                line.push(part);
             }
         }
-        println!();
-        println!("{}", self.score());
-        Ok(())
+        println!("{}", line.join(" "));
+    }
+}
+
+const MIN_TERM_WIDTH: u16 = 46;
+const MIN_TERM_HEIGHT: u16 = 24;
+
+// the upper section up to where `PlayerState::display` starts the lower one,
+// then the lower rows, which grow with every column
+fn board_width(columns: usize, palette: Palette) -> u16 {
+    let marker_width = palette.marker(0).width();
+    let lower_column = 18 + columns * (5 + marker_width) - marker_width;
+    (lower_column + 15 + columns * (5 + marker_width)) as u16
+}
+
+fn wait_for_term_size(
+    term: &console::Term,
+    columns: usize,
+    palette: Palette,
+) -> std::io::Result<()> {
+    if !term.is_term() {
+        return Ok(());
+    }
+    let min_width = MIN_TERM_WIDTH.max(board_width(columns, palette));
+    let mut shown = None;
+    loop {
+        let (rows, cols) = term.size();
+        if rows >= MIN_TERM_HEIGHT && cols >= min_width {
+            return Ok(());
+        }
+        if shown != Some((rows, cols)) {
+            term.clear_screen()?;
+            println!(
+                "Please resize your terminal to at least {min_width}×{MIN_TERM_HEIGHT} \
+                 (currently {cols}×{rows}), or press Ctrl-C to quit."
+            );
+            shown = Some((rows, cols));
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+// Prompts hide the cursor and the board is printed with styles, so leaving
+// in the middle of either, e.g. through an error, a panic or Ctrl-C, would
+// otherwise leave the shell without a cursor or in some color.
+struct TerminalGuard(console::Term);
+
+impl TerminalGuard {
+    fn new(term: console::Term) -> Self {
+        #[cfg(unix)]
+        {
+            extern "C" fn restore(signal: libc::c_int) {
+                const RESET: &[u8] = b"\x1b[0m\x1b[?25h\n";
+                // only async-signal-safe calls in here; dying by the same
+                // signal afterwards keeps the exit status the shell expects
+                unsafe {
+                    if let Some(saved) = SAVED_TERMIOS.get() {
+                        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+                    }
+                    libc::write(libc::STDOUT_FILENO, RESET.as_ptr().cast(), RESET.len());
+                    libc::signal(signal, libc::SIG_DFL);
+                    libc::raise(signal);
+                }
+            }
+            for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+                unsafe {
+                    libc::signal(signal, restore as *const () as libc::sighandler_t);
+                }
+            }
+        }
+        Self(term)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if console::colors_enabled() {
+            let _ = self.0.write_str("\x1b[0m");
+        }
+        let _ = self.0.show_cursor();
     }
 }
 
 fn main() -> std::io::Result<()> {
+    let options = Options::from_args().unwrap_or_else(|message| {
+        eprintln!("{message}");
+        std::process::exit(2);
+    });
+    NAMES.set(options.names.clone()).unwrap();
+    if let Some(path) = &options.evaluate {
+        let score = read_scorecard(path)
+            .and_then(|assignments| {
+                evaluate_scorecard(&assignments, &options.rules).map_err(|e| e.to_string())
+            })
+            .unwrap_or_else(|message| {
+                eprintln!("{message}");
+                std::process::exit(2);
+            });
+        println!("{score}");
+        return Ok(());
+    }
+    if let Some(seed) = options.playthrough {
+        let mut source = random_source(Some(seed), options.dice_weights);
+        return greedy_playthrough(
+            &mut PlayerState::new(options.rules.columns),
+            &mut *source,
+            &options.rules,
+            options.bonus_weighting,
+            options.ai_difficulty,
+            Some(seed),
+        );
+    }
+    if options.json_protocol {
+        let mut source = random_source(options.seed, options.dice_weights);
+        return play_protocol(
+            &mut *source,
+            &options.rules,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout(),
+        );
+    }
+    if let Some(path) = &options.edit_rules {
+        let _guard = TerminalGuard::new(console::Term::stdout());
+        return edit_rules(options.rules, path);
+    }
+    if options.metrics.is_some() && options.stats.is_none() {
+        eprintln!("--metrics needs --stats <FILE>");
+        std::process::exit(2);
+    }
+    if options.sacrifice_report {
+        let Some(path) = &options.stats else {
+            eprintln!("--sacrifice-report needs --stats <FILE>");
+            std::process::exit(2);
+        };
+        Stats::load(path)?.display_sacrifices();
+        return Ok(());
+    }
+    let load = |path: &Path| {
+        read_scorecard(path)
+            .and_then(|assignments| {
+                load_scorecard(&assignments, &options.rules).map_err(|e| e.to_string())
+            })
+            .unwrap_or_else(|message| {
+                eprintln!("{message}");
+                std::process::exit(2);
+            })
+    };
+    if let Some((a, b)) = &options.compare {
+        compare_scorecards(&load(a), &load(b), &options.rules, options.palette);
+        return Ok(());
+    }
+    if let Some(path) = &options.solve {
+        let start = load(path);
+        let seed = options.seed.unwrap_or_else(|| fastrand::u64(..));
+        println!("Seed: {seed}");
+        let mut source = random_source(Some(seed), options.dice_weights);
+        let mut player_state = start.clone();
+        GreedyPlayer::new(
+            &options.rules,
+            options.bonus_weighting,
+            Difficulty::Hard,
+            None,
+        )
+        .play(
+            &mut player_state,
+            &mut *source,
+            &options.rules,
+            &mut std::io::stdout(),
+        )?;
+        // the sample game used `seed` itself, the others follow it
+        let solved = solve_scorecard(
+            &start,
+            &options.rules,
+            options.bonus_weighting,
+            seed.wrapping_add(1),
+            options.dice_weights,
+        )?;
+        if let Some((combination, count, expected)) = solved {
+            println!();
+            println!("Next turn: record {combination} ({count} of {SOLVE_GAMES} games do)");
+            println!("Expected final total: {expected:.1}");
+        }
+        return Ok(());
+    }
     let term = console::Term::stdout();
-    let mut player_state = PlayerState::new();
+    let _guard = TerminalGuard::new(term.clone());
+    if !options.compact {
+        wait_for_term_size(&term, options.rules.columns, options.palette)?;
+    }
+    if options.scoresheet {
+        let player_state =
+            keep_scoresheet(&term, &options.rules, options.palette, options.strike_zeros)?;
+        term.clear_screen()?;
+        player_state.display(&options.rules, options.palette, false, options.strike_zeros)?;
+        display_trophy(&player_state, &options.rules, options.palette);
+        return Ok(());
+    }
+    // random games always get a seed, so that they can be replayed later
+    let game_seed = match options.dice_source {
+        DiceSourceKind::Random => Some(options.seed.unwrap_or_else(clock_seed)),
+        _ => None,
+    };
+    let mut source: Box<dyn DiceSource> = match &options.dice_source {
+        DiceSourceKind::Random => random_source(game_seed, options.dice_weights),
+        DiceSourceKind::Manual => Box::new(ManualDice),
+        DiceSourceKind::File(path) => Box::new(FileDice::new(path.clone())),
+    };
+    if options.debug_dice {
+        source = Box::new(RepeatWatch::new(source));
+    }
+    let mut source = FaceTally::new(source);
+    let broadcast = options
+        .broadcast
+        .as_deref()
+        .map(Broadcast::bind)
+        .transpose()?;
+    let mut player_state = PlayerState::new(options.rules.columns);
+    // separate from the dice, so that shuffling does not change what is
+    // rolled for a seed
+    let mut menu_rng = match game_seed.or(options.seed) {
+        Some(seed) => fastrand::Rng::with_seed(seed),
+        None => fastrand::Rng::new(),
+    };
+    let mut endgame = Endgame::new(&options.rules);
+    // records for --show-ai below hard, which must not solve the endgame the
+    // hints still do
+    let mut heuristic = Endgame::new(&options.rules);
+    heuristic.solvable = false;
+    // only built once someone asks for it
+    let odds = OnceCell::new();
+    let mut record_timings = Vec::new();
+    let mut reroll_gains = Vec::new();
+    // with --show-ai, how many records were the computer's choice too
+    let (mut ai_matches, mut ai_records) = (0, 0);
+    let mut coaching = None;
+    // with --no-clear, every screen is printed below the previous one
+    let clear_screen = || {
+        if options.no_clear {
+            println!();
+            Ok(())
+        } else {
+            term.clear_screen()
+        }
+    };
     'outer: loop {
-        let mut dice: DiceRolls = std::array::from_fn(|_| DIE_ROLLS[fastrand::usize(..6)]);
-        let mut valued_combinations = Vec::new();
+        if let Some(broadcast) = &broadcast {
+            broadcast.send(player_state.snapshot(&options.rules));
+        }
+        let mut dice = roll_turn(&mut source, &player_state, &options.rules)?;
+        let mut first_roll = dice;
+        let mut valued_combinations: Vec<ValuedCombination>;
+        let mut target = None;
+        let mut target_asked = false;
+        let mut restarted = false;
+        let mut undone = None;
+        // asked for from the record menu, which has no Esc of its own
+        let mut show_turn_menu = false;
+        let mut rolled = vec![0, 1, 2, 3, 4];
+        let rerolls = options.rules.rerolls;
         let mut i = 0;
-        loop {
-            term.clear_screen()?;
-            player_state.display(&term)?;
-            if player_state.is_done() {
-                break 'outer;
+        // the record menu can go back to rolling while rolls are left
+        'turn: loop {
+            loop {
+                if options.compact {
+                    clear_screen()?;
+                    println!(
+                        "{} | {}/{}",
+                        player_state.score(&options.rules).compact(),
+                        player_state.filled.len(),
+                        options.rules.total_boxes() * options.rules.columns
+                    );
+                } else {
+                    wait_for_term_size(&term, options.rules.columns, options.palette)?;
+                    clear_screen()?;
+                    player_state.display(
+                        &options.rules,
+                        options.palette,
+                        options.show_gain,
+                        options.strike_zeros,
+                    )?;
+                    println!();
+                }
+                if player_state.is_done(&options.rules) {
+                    if let Some(coaching) = coaching.take() {
+                        println!("{coaching}");
+                    }
+                    break 'outer;
+                }
+                i += 1;
+                if let Some(duration) = options.animate.filter(|_| term.is_term()) {
+                    animate_roll(&term, &dice, &rolled, duration)?;
+                }
+                rolled.clear();
+                dice.sort();
+                let keep = match target {
+                    Some(Combination::Chance) if i <= rerolls => {
+                        Some(best_chance_keep(dice, rerolls + 1 - i))
+                    }
+                    Some(target) if i <= rerolls => {
+                        Some(best_keep_for(target, dice, rerolls + 1 - i))
+                    }
+                    None if i <= rerolls => endgame
+                        .state(&player_state)
+                        .map(|(open, upper)| endgame.best_keep(dice, rerolls + 1 - i, open, upper)),
+                    _ => None,
+                };
+                let marked = |idx| matches!(&keep, Some((keep, _)) if keep.contains(&idx));
+                if options.big_dice && term.size().1 >= BIG_DICE_WIDTH {
+                    println!("You rolled:");
+                    display_big_dice(&dice, marked, options.palette);
+                } else {
+                    print!("You rolled:");
+                    for (idx, die) in dice.iter().enumerate() {
+                        if marked(idx) && options.palette.marks_suggested() {
+                            print!(" [{}]", options.palette.suggested(die));
+                        } else if marked(idx) {
+                            print!(" {}", options.palette.suggested(die));
+                        } else {
+                            print!(" {die}");
+                        }
+                    }
+                    println!();
+                }
+                if let Some(seed) = game_seed.filter(|_| player_state.filled.is_empty()) {
+                    println!("Game ID: {}", game_id(seed));
+                }
+                if let Some(coaching) = coaching.take() {
+                    println!("{coaching}");
+                }
+                if let Some((from, to)) = undone.take() {
+                    println!(
+                        "Rolling again would have lowered your best score from {from} to {to}"
+                    );
+                }
+                let counts = DiceCounts::new(dice);
+                let values = PotentialValues::new(counts, &options.rules.scoring);
+                valued_combinations = player_state.ranked_combinations(&options.rules, dice);
+                let turn_menu_asked = std::mem::take(&mut show_turn_menu);
+                if i > rerolls && !turn_menu_asked {
+                    break;
+                }
+                for vc in &valued_combinations {
+                    if vc.value == 0 || options.compact {
+                        break;
+                    }
+                    let hint = match vc.combination {
+                        Combination::Upper(die) if options.bonus_hints => {
+                            bonus_hint(&player_state, &options.rules, die, vc.value)
+                        }
+                        _ => None,
+                    };
+                    let hint = hint.map(|hint| format!("  {hint}")).unwrap_or_default();
+                    if options.percentages {
+                        let max = options.rules.scoring.max_value(vc.combination);
+                        let percent = 100 * vc.value as u32 / max as u32;
+                        println!("{vc} {}/{max} = {percent}%{hint}", vc.value);
+                    } else {
+                        println!("{vc}{hint}");
+                    }
+                }
+                let best = values.best(player_state.open_combinations(&options.rules));
+                if let (false, Some((best, value))) = (options.compact, best) {
+                    println!("Best right now: {best} ({value})");
+                }
+                // no roll can score more than the best open box already does
+                let open_max = player_state
+                    .open_combinations(&options.rules)
+                    .map(|combination| options.rules.scoring.max_value(combination))
+                    .max();
+                if let (Some((_, value)), Some(max)) = (best, open_max) {
+                    if value >= max {
+                        println!("Best possible already — record now?");
+                    }
+                }
+                if options.target && i == 1 && !target_asked && !turn_menu_asked {
+                    let targets: Vec<Combination> = valued_combinations
+                        .iter()
+                        .map(|vc| vc.combination)
+                        .collect();
+                    let mut items: Vec<String> = targets.iter().map(|c| c.to_string()).collect();
+                    items.push("nothing in particular".to_string());
+                    let selection = dialoguer::Select::new()
+                        .with_prompt("What are you going for?")
+                        .items(&items)
+                        .default(items.len() - 1)
+                        .interact()
+                        .unwrap();
+                    target = targets.get(selection).copied();
+                    target_asked = true;
+                    // show the same roll again, now with the suggestion marked
+                    i -= 1;
+                    continue;
+                }
+                if let (Some(target), Some((keep, odds))) = (target, &keep) {
+                    let odds = if target == Combination::Chance {
+                        format!("expected {odds:.1}")
+                    } else {
+                        format!("{:.0}% chance", odds * 100.0)
+                    };
+                    if keep.is_empty() {
+                        println!("To go for {target}, roll all dice again ({odds})");
+                    } else {
+                        println!("To go for {target}, keep the marked dice ({odds})");
+                    }
+                }
+                if let (Some(target), Some((_, odds))) = (target, &keep) {
+                    let miss = 1.0 - odds;
+                    if target != Combination::Chance && miss > 0.0 {
+                        let scoring = &options.rules.scoring;
+                        let cheapest =
+                            &valued_combinations[cheapest_sacrifice(&valued_combinations, scoring)];
+                        let loss = miss * scoring.max_value(cheapest.combination) as f64;
+                        println!(
+                            "Risk: may lose ~{loss:.0} pts ({:.0}% miss, then crossing out {})",
+                            miss * 100.0,
+                            cheapest.combination
+                        );
+                    }
+                }
+                if let (None, Some((keep, expected))) = (target, &keep) {
+                    if keep.len() == dice.len() {
+                        println!("Best play: stop rolling (expect {expected:.1} more points)");
+                    } else {
+                        println!(
+                            "Best play: keep the marked dice (expect {expected:.1} more points)"
+                        );
+                    }
+                }
+                let best_value = |dice: DiceRolls| {
+                    PotentialValues::new(DiceCounts::new(dice), &options.rules.scoring)
+                        .best(player_state.open_combinations(&options.rules))
+                        .map_or(0, |(_, value)| value)
+                };
+                let before = dice;
+                let selection = if turn_menu_asked {
+                    None
+                } else if options.incremental {
+                    match reroll_incrementally(&mut dice, &mut source)? {
+                        Some(false) => break,
+                        Some(true) => {
+                            if options.rules.must_improve && best_value(dice) < best_value(before) {
+                                undone = Some((best_value(before), best_value(dice)));
+                                dice = before;
+                            }
+                            continue;
+                        }
+                        None => None,
+                    }
+                } else if options.lock_mode {
+                    dialoguer::MultiSelect::new()
+                        .with_prompt(
+                            "Select the dice that you want to KEEP (unchecked dice are rolled again, \
+                             Esc for more)",
+                        )
+                        .items(&dice)
+                        .defaults(&[true; 5])
+                        .interact_opt()
+                        .unwrap()
+                        .map(|kept| (0..dice.len()).filter(|idx| !kept.contains(idx)).collect())
+                } else {
+                    dialoguer::MultiSelect::new()
+                        .with_prompt("Select the dice that you want to roll again (Esc for more)")
+                        .items(&dice)
+                        .interact_opt()
+                        .unwrap()
+                };
+                let Some(selection) = selection else {
+                    match turn_menu(restarted) {
+                        TurnAction::Back => {
+                            // show the same roll again
+                            i -= 1;
+                        }
+                        TurnAction::Restart => {
+                            restarted = true;
+                            source.roll(&mut dice, &[0, 1, 2, 3, 4])?;
+                            rolled = vec![0, 1, 2, 3, 4];
+                            first_roll = dice;
+                            i = 0;
+                        }
+                        TurnAction::Pause => {
+                            term.clear_screen()?;
+                            println!("Paused — press any key to continue");
+                            term.read_key()?;
+                            i -= 1;
+                        }
+                        TurnAction::Share => {
+                            let best = values.best(player_state.open_combinations(&options.rules));
+                            println!("{}", share_text(&dice, best));
+                            println!("Press any key to continue");
+                            term.read_key()?;
+                            i -= 1;
+                        }
+                        TurnAction::Odds => {
+                            display_odds_table(
+                                &term,
+                                odds.get_or_init(|| odds_table(&options.rules)),
+                            )?;
+                            i -= 1;
+                        }
+                        TurnAction::Takeover => {
+                            // the computer starts over with a fresh turn
+                            clear_screen()?;
+                            greedy_playthrough(
+                                &mut player_state,
+                                &mut source,
+                                &options.rules,
+                                options.bonus_weighting,
+                                options.ai_difficulty,
+                                game_seed.or(options.seed),
+                            )?;
+                            println!("Press any key to see the final scorecard");
+                            term.read_key()?;
+                            clear_screen()?;
+                            player_state.display(
+                                &options.rules,
+                                options.palette,
+                                false,
+                                options.strike_zeros,
+                            )?;
+                            println!();
+                            break 'outer;
+                        }
+                        TurnAction::Quit => {
+                            let total = player_state.score(&options.rules).total();
+                            let confirmed = dialoguer::Confirm::new()
+                                .with_prompt(format!("Quit the game with {total} points?"))
+                                .interact()
+                                .unwrap();
+                            if confirmed {
+                                clear_screen()?;
+                                player_state.display(
+                                    &options.rules,
+                                    options.palette,
+                                    false,
+                                    options.strike_zeros,
+                                )?;
+                                println!();
+                                println!("Game forfeited with {total} points");
+                                if let Some(seed) = game_seed {
+                                    println!("Game ID: {}", game_id(seed));
+                                }
+                                return Ok(());
+                            }
+                            i -= 1;
+                        }
+                    }
+                    continue;
+                };
+                if options.reroll_preview && !selection.is_empty() {
+                    let (best, worst, average) = reroll_spread(&dice, &selection, best_value);
+                    println!("Reroll → best {best} / worst {worst} / avg {average:.0}");
+                }
+                if options.confirm_reroll || options.reroll_preview {
+                    match confirm_reroll(&dice, &selection) {
+                        RerollChoice::Reroll => {}
+                        RerollChoice::Record => break,
+                        RerollChoice::Change => {
+                            i -= 1;
+                            continue;
+                        }
+                    }
+                }
+                if let (Some(threshold), Some((open, upper))) =
+                    (options.study, endgame.state(&player_state))
+                {
+                    let rerolls_left = rerolls + 1 - i;
+                    let kept: Vec<usize> = (0..dice.len())
+                        .filter(|idx| !selection.contains(idx))
+                        .collect();
+                    let (best, best_expected) = endgame.best_keep(dice, rerolls_left, open, upper);
+                    let expected = endgame.keep_value(dice, &kept, rerolls_left, open, upper);
+                    if best_expected - expected > threshold {
+                        let best: Vec<String> =
+                            best.iter().map(|&idx| dice[idx].to_string()).collect();
+                        println!(
+                            "Keeping [{}] instead would be worth {:.1} more points",
+                            best.join(" "),
+                            best_expected - expected
+                        );
+                        let confirmed = dialoguer::Confirm::new()
+                            .with_prompt("Go ahead anyway?")
+                            .default(false)
+                            .interact()
+                            .unwrap();
+                        if !confirmed {
+                            i -= 1;
+                            continue;
+                        }
+                    }
+                }
+                if selection.is_empty() {
+                    break;
+                }
+                source.roll(&mut dice, &selection)?;
+                rolled = selection;
+                if options.rules.must_improve && best_value(dice) < best_value(before) {
+                    undone = Some((best_value(before), best_value(dice)));
+                    dice = before;
+                }
             }
-            i += 1;
-            dice.sort();
-            println!();
-            print!("You rolled:");
-            for die in dice {
-                print!(" {die}");
+            options.rules.filter_recordable(&mut valued_combinations, i);
+            if options.shuffle_menu {
+                menu_rng.shuffle(&mut valued_combinations);
             }
-            println!();
-            let counts = DiceCounts::new(dice);
-            let values = PotentialValues::new(counts);
-            valued_combinations.clear();
-            for number in DIE_ROLLS {
-                let combination = Combination::Upper(number);
-                valued_combinations.push(ValuedCombination {
-                    combination,
-                    value: values[combination],
-                });
+            if let Some((open, upper)) = endgame.state(&player_state) {
+                let (best, expected) = endgame.best_record(dice, open, upper);
+                println!("Best play: record {best} (expect {expected:.1} more points)");
             }
-            for combination in LOWER_COMBINATIONS {
-                valued_combinations.push(ValuedCombination {
-                    combination,
-                    value: values[combination],
-                });
+            let mut ai_choice = None;
+            if options.show_ai {
+                let ai_endgame = if options.ai_difficulty == Difficulty::Hard {
+                    &mut endgame
+                } else {
+                    &mut heuristic
+                };
+                let choice = computer_record(
+                    &valued_combinations,
+                    dice,
+                    &player_state,
+                    &options.rules,
+                    ai_endgame,
+                    options.bonus_weighting,
+                );
+                let vc = &valued_combinations[choice];
+                println!(
+                    "(the computer would record: {} {})",
+                    vc.combination, vc.value
+                );
+                ai_choice = Some(vc.combination);
             }
-            valued_combinations.retain(|vc| !player_state.has_combination(vc.combination));
-            valued_combinations.sort_by_key(|vc| 100 - vc.value);
-            if i > 2 {
-                break;
+            if let Some(tradeoff) = quintuple_tradeoff(&player_state, &options.rules, dice) {
+                println!("{tradeoff}");
             }
-            for vc in &valued_combinations {
-                if vc.value == 0 {
-                    break;
+            let started = Instant::now();
+            let mut sacrifice = None;
+            if options.auto_sacrifice && valued_combinations.iter().all(|vc| vc.value == 0) {
+                let cheapest = cheapest_sacrifice(&valued_combinations, &options.rules.scoring);
+                let confirmed = dialoguer::Confirm::new()
+                    .with_prompt(format!(
+                        "Nothing scores. Cross out {}?",
+                        valued_combinations[cheapest].combination
+                    ))
+                    .default(true)
+                    .interact()
+                    .unwrap();
+                if confirmed {
+                    sacrifice = Some(cheapest);
                 }
-                println!("{vc}");
             }
-            let selection = dialoguer::MultiSelect::new()
-                .with_prompt("Select the dice that you want to roll again")
-                .items(&dice)
-                .interact()
-                .unwrap();
-            if selection.is_empty() {
-                break;
+            // the items after the boxes
+            let back = valued_combinations.len();
+            let more = back + usize::from(i <= rerolls);
+            loop {
+                let selection = match sacrifice.take() {
+                    Some(selection) => selection,
+                    None if options.shortcuts => {
+                        record_by_shortcut(&term, &valued_combinations)?.unwrap_or(more)
+                    }
+                    None => {
+                        let mut items: Vec<String> = valued_combinations
+                            .iter()
+                            .map(|vc| vc.to_string())
+                            .collect();
+                        if i <= rerolls {
+                            items.push(format!("back to rolling ({} left)", rerolls + 1 - i));
+                        }
+                        items.push("more…".to_string());
+                        dialoguer::Select::new()
+                            .with_prompt("What combination do you want to record?")
+                            .items(&items)
+                            .interact()
+                            .unwrap()
+                    }
+                };
+                if selection >= back {
+                    show_turn_menu = selection == more;
+                    // show the same roll again
+                    i -= 1;
+                    continue 'turn;
+                }
+                let mut vc = valued_combinations[selection].clone();
+                // the expected points given up compared to the best box
+                let advice = endgame
+                    .state(&player_state)
+                    .filter(|_| options.coach || options.study.is_some())
+                    .and_then(|(open, upper)| {
+                        let (best, best_expected) = endgame.best_record(dice, open, upper);
+                        endgame
+                            .record_values(dice, open, upper)
+                            .into_iter()
+                            .find(|&(combination, _)| combination == vc.combination)
+                            .map(|(_, expected)| (best, best_expected - expected))
+                    });
+                if let (Some(threshold), Some((best, loss))) = (options.study, advice) {
+                    if loss > threshold {
+                        println!("Recording {best} instead would be worth {loss:.1} more points");
+                        let confirmed = dialoguer::Confirm::new()
+                            .with_prompt(format!("Record {} anyway?", vc.combination))
+                            .default(false)
+                            .interact()
+                            .unwrap();
+                        if !confirmed {
+                            continue;
+                        }
+                    }
+                }
+                if options.coach {
+                    coaching = advice.map(|(best, loss)| {
+                        if loss < 0.05 {
+                            format!("Recording {} was the best play", vc.combination)
+                        } else {
+                            format!(
+                                "Recording {best} instead of {} would have been worth {loss:.1} \
+                                 more points",
+                                vc.combination
+                            )
+                        }
+                    });
+                }
+                vc.column = choose_column(&player_state, vc.combination, "record");
+                vc.natural = vc.value > 0 && is_natural(vc.combination, first_roll);
+                let first_values =
+                    PotentialValues::new(DiceCounts::new(first_roll), &options.rules.scoring);
+                let gain = vc.value as i32 - first_values[vc.combination] as i32;
+                if options.notes {
+                    let note: String = dialoguer::Input::new()
+                        .with_prompt("Note (optional)")
+                        .allow_empty(true)
+                        .interact_text()
+                        .unwrap();
+                    if !note.trim().is_empty() {
+                        vc.note = Some(note.trim().to_string());
+                    }
+                }
+                let matched_ai = ai_choice.map(|combination| combination == vc.combination);
+                // the menus only offer open boxes, but if one slips through, ask again
+                // instead of giving up on the game
+                match player_state.record_value(vc) {
+                    Ok(()) => {
+                        record_timings.push(started.elapsed());
+                        reroll_gains.push((i - 1, gain));
+                        if let Some(matched) = matched_ai {
+                            ai_records += 1;
+                            ai_matches += matched as u32;
+                        }
+                        break 'turn;
+                    }
+                    Err(error) => println!("{error}, please choose another combination"),
+                }
             }
-            for idx in selection {
-                dice[idx] = DIE_ROLLS[fastrand::usize(..6)];
+        }
+    }
+
+    display_trophy(&player_state, &options.rules, options.palette);
+
+    let naturals: Vec<String> = player_state
+        .filled
+        .iter()
+        .filter(|vc| vc.natural)
+        .map(|vc| vc.combination.to_string())
+        .collect();
+    if !naturals.is_empty() {
+        println!("  Rolled outright: {}", naturals.join(", "));
+    }
+    for column in (0..player_state.columns).filter(|&column| player_state.perfect_upper(column)) {
+        let place = if player_state.columns > 1 {
+            format!(" in column {}", column + 1)
+        } else {
+            String::new()
+        };
+        let message = format!("★ Perfect upper section{place}: five of every face! ★");
+        println!("  {}", options.palette.trophy(message));
+    }
+
+    let hot = DIE_ROLLS
+        .into_iter()
+        .max_by_key(|&die| source.count(die))
+        .unwrap();
+    let cold = DIE_ROLLS
+        .into_iter()
+        .min_by_key(|&die| source.count(die))
+        .unwrap();
+    println!(
+        "  Hottest face: {hot} ({}×), coldest face: {cold} ({}×)",
+        source.count(hot),
+        source.count(cold)
+    );
+    if let Some(seed) = game_seed {
+        println!("  Game ID: {} (replay with --seed)", game_id(seed));
+    }
+    if ai_records > 0 {
+        println!("  You matched the computer on {ai_matches}/{ai_records} records.");
+    }
+
+    #[cfg(feature = "svg")]
+    if let Some(path) = &options.export_image {
+        export_svg(&player_state, &options.rules, path)?;
+    }
+
+    if options.rule_variants {
+        display_rule_variants(&player_state, &options.rules);
+    }
+
+    if options.timings {
+        display_timings(&record_timings);
+    }
+
+    if options.reroll_stats {
+        display_reroll_efficiency(&reroll_gains);
+    }
+
+    if let Some(path) = &options.stats {
+        let mut stats = Stats::load(path)?;
+        stats.add_game(&player_state, &options.rules);
+        stats.save(path)?;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &options.metrics {
+            stats.export_metrics(metrics)?;
+        }
+    }
+
+    if options.notes {
+        let (upper, lower) = player_state.by_section();
+        println!();
+        for ValuedCombination {
+            combination,
+            column,
+            value,
+            note,
+            ..
+        } in upper.into_iter().chain(lower)
+        {
+            let column = if player_state.columns > 1 {
+                format!(" column {}", column + 1)
+            } else {
+                String::new()
+            };
+            match note {
+                Some(note) => println!("{combination:15} ({value:2}){column}  {note}"),
+                None => println!("{combination:15} ({value:2}){column}"),
             }
         }
-        let selection = dialoguer::Select::new()
-            .with_prompt("What combination do you want to record?")
-            .items(&valued_combinations)
-            .interact()
-            .unwrap();
-        player_state
-            .record_value(valued_combinations[selection])
-            .expect("recorded combination should not have been selectable");
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(combination: Combination, value: u16) -> ValuedCombination {
+        ValuedCombination {
+            combination,
+            column: 0,
+            value,
+            note: None,
+            natural: false,
+            dice: [DieRoll::One; 5],
+        }
+    }
+
+    // three of every face, with `ones` in the ones box instead
+    fn upper_state(ones: u16) -> PlayerState {
+        let mut player_state = PlayerState::new(1);
+        for die in DIE_ROLLS {
+            let value = if die == DieRoll::One {
+                ones
+            } else {
+                3 * die as u16
+            };
+            player_state
+                .record_value(recorded(Combination::Upper(die), value))
+                .unwrap();
+        }
+        player_state
+    }
+
+    #[test]
+    fn bonus_threshold() {
+        for (ones, upper, bonus) in [(2, 62, 0), (3, 63, 35), (4, 64, 35)] {
+            let score = upper_state(ones).score(&Rules::default());
+            assert_eq!(score.upper, upper);
+            assert_eq!(score.bonus, bonus);
+        }
+    }
+
+    // a die rolled once is worth 3.5, one that may be rolled twice is kept
+    // on 4 or more and worth 4.25
+    #[test]
+    fn chance_ev_per_die() {
+        assert!((chance_ev(&[], 1) - 5.0 * 3.5).abs() < 1e-9);
+        assert!((chance_ev(&[], 2) - 5.0 * 4.25).abs() < 1e-9);
+        assert!((chance_ev(&[DieRoll::Six], 2) - (6.0 + 4.0 * 4.25)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn forced_upper_on_first_roll() {
+        let rules = Rules {
+            forced_upper: true,
+            ..Rules::default()
+        };
+        let boxes = || {
+            vec![
+                recorded(Combination::Upper(DieRoll::Three), 9),
+                recorded(Combination::FullHouse, 25),
+                recorded(Combination::Chance, 19),
+            ]
+        };
+        let mut first = boxes();
+        rules.filter_recordable(&mut first, 1);
+        assert!(first.iter().all(|vc| vc.combination.is_upper()));
+        assert_eq!(first.len(), 1);
+        let mut second = boxes();
+        rules.filter_recordable(&mut second, 2);
+        assert_eq!(second.len(), 3);
+        // once the upper section is full, the lower boxes are left
+        let mut lower_only = boxes().split_off(1);
+        rules.filter_recordable(&mut lower_only, 1);
+        assert_eq!(lower_only.len(), 2);
+        let mut unforced = boxes();
+        Rules::default().filter_recordable(&mut unforced, 1);
+        assert_eq!(unforced.len(), 3);
+    }
+
+    struct Padded(&'static str);
+
+    impl Display for Padded {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            pad_to_width(f, self.0)
+        }
+    }
+
+    #[test]
+    fn pad_to_width_counts_columns() {
+        assert_eq!(format!("{:6}", Padded("abc")), "abc   ");
+        // wide glyphs take two columns, combining marks none
+        assert_eq!(format!("{:6}", Padded("日本")), "日本  ");
+        assert_eq!(format!("{:4}", Padded("e\u{301}")), "e\u{301}   ");
+        assert_eq!(format!("{:>5}", Padded("日本")), " 日本");
+        assert_eq!(format!("{:^6}", Padded("ab")), "  ab  ");
+        assert_eq!(format!("{:2}", Padded("日本")), "日本");
+        for s in ["abc", "日本", "e\u{301}"] {
+            assert_eq!(format!("{:8}", Padded(s)).width(), 8);
+        }
+    }
+
+    #[test]
+    fn no_bonus_above_threshold() {
+        let rules = Rules {
+            bonus: BonusMode::None,
+            ..Rules::default()
+        };
+        let player_state = upper_state(5);
+        let score = player_state.score(&rules);
+        assert_eq!(score.upper, 65);
+        assert_eq!(score.bonus, 0);
+        assert_eq!(score.total(), 65);
+        assert_eq!(player_state.bonus_chance(&rules, 0), None);
+    }
+
+    fn roll(faces: [u8; 5]) -> DiceCounts {
+        DiceCounts::new(faces.map(|face| DieRoll::from_u8(face).unwrap()))
+    }
+
+    #[test]
+    fn full_house_as_sum() {
+        let flat = PotentialValues::new(roll([2, 2, 2, 5, 5]), &ScoringTable::default());
+        assert_eq!(flat[Combination::FullHouse], 25);
+        let scoring = ScoringTable {
+            full_house_as_sum: true,
+            ..ScoringTable::default()
+        };
+        let sum = PotentialValues::new(roll([2, 2, 2, 5, 5]), &scoring);
+        assert_eq!(sum[Combination::FullHouse], 16);
+        let no_full_house = PotentialValues::new(roll([2, 2, 2, 5, 6]), &scoring);
+        assert_eq!(no_full_house[Combination::FullHouse], 0);
+    }
+
+    #[test]
+    fn capped_chance() {
+        let high = || roll([6, 6, 6, 5, 5]);
+        let chance = |chance| {
+            let scoring = ScoringTable {
+                chance,
+                ..ScoringTable::default()
+            };
+            PotentialValues::new(high(), &scoring)[Combination::Chance]
+        };
+        assert_eq!(chance(ChanceScoring::Sum), 28);
+        assert_eq!(chance(ChanceScoring::Capped(20)), 20);
+        assert_eq!(chance(ChanceScoring::Capped(30)), 28);
+        assert_eq!(chance(ChanceScoring::Fixed(25)), 25);
+        let scoring = ScoringTable {
+            chance: ChanceScoring::Capped(20),
+            ..ScoringTable::default()
+        };
+        assert_eq!(scoring.max_value(Combination::Chance), 20);
+    }
+
+    #[test]
+    fn parse_dice_errors() {
+        assert_eq!(parse_dice("7"), Err(GameError::FaceOutOfRange(7)));
+        assert_eq!(parse_dice("1 2 7 4 5"), Err(GameError::FaceOutOfRange(7)));
+        assert_eq!(
+            parse_dice("1 2 3 4"),
+            Err(GameError::WrongDiceCount {
+                expected: 5,
+                found: 4
+            })
+        );
+        assert_eq!(parse_dice("x"), Err(GameError::InvalidDie('x')));
+        assert_eq!(parse_dice("1 2 x 4 5"), Err(GameError::InvalidDie('x')));
+        // separated numbers are read as a whole
+        assert_eq!(parse_dice("12 3 4 5"), Err(GameError::FaceOutOfRange(12)));
+        assert_eq!(parse_dice("16,2,3,4"), Err(GameError::FaceOutOfRange(16)));
+        let dice = [1, 3, 3, 4, 6].map(|face| DieRoll::from_u8(face).unwrap());
+        for input in ["13346", "1 3 3 4 6", "1,3,3,4,6", " 1, 3 3,4 6 "] {
+            assert_eq!(parse_dice(input), Ok(dice));
+        }
+    }
+
+    // only one box left, the endgame agrees with playing for that box alone
+    #[test]
+    fn endgame_single_box() {
+        let rules = Rules::default();
+        let mut endgame = Endgame::new(&rules);
+        let bit = |combination| {
+            let idx = rules.combinations().position(|c| c == combination).unwrap();
+            1 << idx
+        };
+        let chance = endgame.expected(bit(Combination::Chance), 0);
+        assert!((chance - chance_ev(&[], 3)).abs() < 1e-9);
+        assert!((chance - 23.33).abs() < 0.01);
+        let kniffel = endgame.expected(bit(Combination::Quintuple), 0);
+        let odds =
+            TargetOdds::new(Combination::Quintuple).keep_probability([DieRoll::One; 5], &[], 3);
+        assert!((kniffel - 50.0 * odds).abs() < 1e-9);
+        assert!((kniffel - 2.30).abs() < 0.01);
+    }
+
+    #[test]
+    fn two_pairs() {
+        let counts = roll([2, 2, 5, 5, 6]);
+        assert!(counts.has_two_pairs());
+        assert_eq!(counts.pairs_sum(), 14);
+        // a full house holds two pairs of different faces
+        let full_house = roll([3, 3, 3, 6, 6]);
+        assert!(full_house.has_two_pairs());
+        assert_eq!(full_house.pairs_sum(), 18);
+        for faces in [
+            [2, 2, 3, 4, 5],
+            [4, 4, 4, 4, 1],
+            [6, 6, 6, 6, 6],
+            [1, 2, 3, 4, 6],
+        ] {
+            assert!(!roll(faces).has_two_pairs());
+        }
+        let values = PotentialValues::new(roll([1, 3, 4, 3, 1]), &ScoringTable::default());
+        assert_eq!(values[Combination::TwoPairs], 8);
+        let values = PotentialValues::new(roll([1, 3, 4, 3, 2]), &ScoringTable::default());
+        assert_eq!(values[Combination::TwoPairs], 0);
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn svg_names_are_escaped() {
+        assert_eq!(xml_escape("R&B <x>"), "R&amp;B &lt;x&gt;");
+    }
+
+    // hands out the faces of `rolls` in order, one roll after the other
+    struct ScriptedDice(VecDeque<DieRoll>);
+
+    impl DiceSource for ScriptedDice {
+        fn roll(&mut self, dice: &mut DiceRolls, indices: &[usize]) -> std::io::Result<()> {
+            for &idx in indices {
+                dice[idx] = self.0.pop_front().expect("the script ran out of dice");
+            }
+            Ok(())
+        }
+    }
+
+    // without rerolls, every turn records one roll of the script
+    fn play_scripted(rules: Rules, rolls: &[[u8; 5]]) -> (PlayerState, Score) {
+        let rules = Rules {
+            rerolls: 0,
+            ..rules
+        };
+        let faces = rolls.iter().flatten();
+        let mut source = ScriptedDice(faces.map(|&face| DieRoll::from_u8(face).unwrap()).collect());
+        let mut player_state = PlayerState::new(1);
+        GreedyPlayer::new(&rules, false, Difficulty::Hard, None)
+            .play(&mut player_state, &mut source, &rules, &mut std::io::sink())
+            .unwrap();
+        assert!(source.0.is_empty());
+        let score = player_state.score(&rules);
+        (player_state, score)
+    }
+
+    const LOWER_ROLLS: [[u8; 5]; 7] = [
+        [6, 6, 6, 6, 6],
+        [1, 2, 3, 4, 5],
+        [2, 3, 4, 5, 6],
+        [2, 2, 3, 3, 3],
+        [1, 2, 4, 5, 6],
+        [6, 6, 6, 5, 2],
+        [6, 6, 6, 6, 3],
+    ];
+
+    #[test]
+    fn scripted_bonus_game() {
+        let upper = [
+            [6, 6, 6, 6, 1],
+            [5, 5, 5, 5, 2],
+            [4, 4, 4, 4, 1],
+            [3, 3, 3, 3, 1],
+            [2, 2, 2, 2, 1],
+            [1, 1, 1, 1, 2],
+        ];
+        let (_, score) = play_scripted(Rules::default(), &[&LOWER_ROLLS[..], &upper].concat());
+        assert_eq!((score.upper, score.bonus, score.lower), (84, 35, 215));
+        assert_eq!(score.total(), 334);
+    }
+
+    #[test]
+    fn scripted_game_without_bonus() {
+        let upper = [
+            [6, 6, 1, 2, 3],
+            [5, 5, 5, 1, 2],
+            [4, 4, 4, 1, 2],
+            [3, 3, 3, 1, 2],
+            [2, 2, 2, 1, 1],
+            [1, 1, 1, 2, 3],
+        ];
+        let (_, score) = play_scripted(Rules::default(), &[&LOWER_ROLLS[..], &upper].concat());
+        assert_eq!((score.upper, score.bonus, score.lower), (57, 0, 215));
+        assert_eq!(score.total(), 272);
+    }
+
+    #[test]
+    fn scripted_game_with_zeros() {
+        // after 5 of a kind and the four boxes worth 30, nothing scores
+        let (player_state, score) = play_scripted(Rules::default(), &[[6; 5]; 13]);
+        assert_eq!(score.bonus, 0);
+        assert_eq!(score.total(), 170);
+        let zeros = player_state
+            .filled
+            .iter()
+            .filter(|vc| vc.value == 0)
+            .count();
+        assert_eq!(zeros, 8);
+    }
+
+    // plays like the game loop: every turn rolls once and records the best
+    // open box, until the game asks for no more dice
+    fn play_turns(source: &mut dyn DiceSource, rules: &Rules) -> PlayerState {
+        let mut player_state = PlayerState::new(rules.columns);
+        loop {
+            let dice = roll_turn(source, &player_state, rules).unwrap();
+            if player_state.is_done(rules) {
+                return player_state;
+            }
+            let values = PotentialValues::new(DiceCounts::new(dice), &rules.scoring);
+            let (combination, value) = values.best(player_state.open_combinations(rules)).unwrap();
+            player_state
+                .record_value(recorded(combination, value))
+                .unwrap();
+        }
+    }
+
+    // a sensor writes one roll per box, the script panics on a 14th roll
+    #[test]
+    fn finished_game_rolls_no_more() {
+        let rules = Rules::default();
+        let faces = [[2, 3, 4, 5, 6]; 13].into_iter().flatten();
+        let mut source = ScriptedDice(faces.map(|face| DieRoll::from_u8(face).unwrap()).collect());
+        let player_state = play_turns(&mut source, &rules);
+        assert_eq!(player_state.filled.len(), 13);
+        assert!(source.0.is_empty());
+    }
+
+    // five of every face, the hot and cold faces must not count a roll
+    // after the last box
+    #[test]
+    fn tally_of_a_finished_game() {
+        let rules = Rules {
+            upper_only: true,
+            ..Rules::default()
+        };
+        let faces = DIE_ROLLS.into_iter().flat_map(|die| [die; 5]);
+        let mut tally = FaceTally::new(Box::new(ScriptedDice(faces.collect())));
+        let player_state = play_turns(&mut tally, &rules);
+        assert_eq!(player_state.score(&rules).upper, 105);
+        for die in DIE_ROLLS {
+            assert_eq!(tally.count(die), 5);
+        }
+    }
+
+    #[test]
+    fn chance_above_hundred_ranks_first() {
+        let rules = Rules {
+            scoring: ScoringTable {
+                chance: ChanceScoring::Fixed(150),
+                ..ScoringTable::default()
+            },
+            ..Rules::default()
+        };
+        let dice = [1, 2, 3, 4, 6].map(|face| DieRoll::from_u8(face).unwrap());
+        let ranked = PlayerState::new(1).ranked_combinations(&rules, dice);
+        assert_eq!(ranked[0].combination, Combination::Chance);
+        assert_eq!(ranked[0].value, 150);
+        assert_eq!(ranked[1].value, 30);
+        assert!(ranked.windows(2).all(|pair| pair[0].value >= pair[1].value));
+    }
+
+    #[test]
+    fn sacrifice_by_scoring_table() {
+        let open = [
+            recorded(Combination::Chance, 0),
+            recorded(Combination::FullHouse, 0),
+        ];
+        assert_eq!(cheapest_sacrifice(&open, &ScoringTable::default()), 1);
+        let scoring = ScoringTable {
+            chance: ChanceScoring::Fixed(10),
+            ..ScoringTable::default()
+        };
+        assert_eq!(cheapest_sacrifice(&open, &scoring), 0);
+    }
+
+    #[test]
+    fn board_width_grows_with_columns() {
+        assert!(board_width(1, Palette::Mono) <= MIN_TERM_WIDTH);
+        assert_eq!(board_width(3, Palette::Default), 63);
+        // a marker after every value, but the lower section may start on the
+        // last upper one
+        assert_eq!(board_width(3, Palette::Mono), 63 + 5 * 2);
+    }
+
+    #[test]
+    fn difficulty_levels() {
+        let rules = Rules::default();
+        let mut one_open = PlayerState::new(1);
+        for combination in rules.combinations().skip(1) {
+            one_open.record_value(recorded(combination, 0)).unwrap();
+        }
+        let solves = |difficulty| {
+            GreedyPlayer::new(&rules, false, difficulty, None)
+                .endgame
+                .state(&one_open)
+                .is_some()
+        };
+        assert!(solves(Difficulty::Hard));
+        assert!(!solves(Difficulty::Medium));
+        assert!(!solves(Difficulty::Easy));
+        // the same dice, some of them kept for the wrong box; a single
+        // reroll keeps the odds quick to work out
+        let rules = Rules {
+            rerolls: 1,
+            ..rules
+        };
+        let average = |difficulty| {
+            let mut player = GreedyPlayer::new(&rules, false, difficulty, Some(1));
+            let total: u16 = (0..10)
+                .map(|seed| {
+                    let mut player_state = PlayerState::new(1);
+                    let mut source = random_source(Some(seed), None);
+                    player
+                        .play(
+                            &mut player_state,
+                            &mut *source,
+                            &rules,
+                            &mut std::io::sink(),
+                        )
+                        .unwrap();
+                    player_state.score(&rules).total()
+                })
+                .sum();
+            total as f64 / 10.0
+        };
+        let (easy, medium) = (average(Difficulty::Easy), average(Difficulty::Medium));
+        assert!(easy < medium);
+    }
+
+    #[test]
+    fn json_values() {
+        assert_eq!(
+            Json::parse(r#" {"a": [1, 22], "b": "x\"é"} "#),
+            Ok(Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![Json::Number(1), Json::Number(22)])
+                ),
+                ("b".to_string(), Json::String("x\"é".to_string())),
+            ]))
+        );
+        assert!(Json::parse(r#"{"a": 1"#).is_err());
+        assert!(Json::parse("[1] 2").is_err());
+        assert_eq!(json_string("a\"b\\\n"), r#""a\"b\\\n""#);
+    }
+
+    #[test]
+    fn protocol_game() {
+        let rules = Rules {
+            upper_only: true,
+            ..Rules::default()
+        };
+        let mut faces = vec![1, 1, 1, 2, 3, 1, 1];
+        for face in 2..=6 {
+            faces.extend([face; 5]);
+        }
+        let mut source = ScriptedDice(
+            faces
+                .into_iter()
+                .map(|face| DieRoll::from_u8(face).unwrap())
+                .collect(),
+        );
+        let record =
+            |combination| format!(r#"{{"command":"record","combination":"{combination}"}}"#);
+        let mut input = vec![
+            r#"{"command":"reroll","indices":[3,4]}"#.to_string(),
+            // not a box of the upper-only game
+            record("chance"),
+        ];
+        for combination in ["ones", "twos", "threes", "fours", "fives", "sixes"] {
+            input.push(record(combination));
+        }
+        let mut out = Vec::new();
+        play_protocol(
+            &mut source,
+            &rules,
+            &mut input.join("\n").as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(
+            lines[0].starts_with(r#"{"type":"state","turn":1,"rolls_left":2,"dice":[1,1,1,2,3],"#)
+        );
+        assert!(lines[1].contains(r#""rolls_left":1,"dice":[1,1,1,1,1]"#));
+        assert!(lines[1].contains(r#""values":{"ones":5,"#));
+        assert_eq!(
+            lines[2],
+            r#"{"type":"error","message":"cannot record chance now"}"#
+        );
+        assert_eq!(
+            lines.last(),
+            Some(&r#"{"type":"done","score":{"upper":105,"bonus":35,"lower":0,"total":140}}"#)
+        );
+        assert!(source.0.is_empty());
+    }
+}